@@ -0,0 +1,272 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::ApiError,
+    book_account::{BookAccount, BookBalance},
+    book_entry::{BookEntry, BookLine},
+    misc::runtime::msg_caller,
+    result::CanisterResult,
+    StaticStorageRef,
+};
+
+/// The outcome of `BookLedger::check_invariants`.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BookInvariantReport {
+    pub entries: u64,
+    pub total_debits_e8s: u128,
+    pub total_credits_e8s: u128,
+}
+
+/// Internal double-entry ledger of user credits in stable storage.
+///
+/// Deposits debit the treasury and credit the user, spending debits the user and credits a revenue account,
+/// refunds debit the user and credit the treasury.
+/// `block_entries` indexes the first entry linked to every block index, so a block is only deposited once.
+#[derive(Clone, Copy)]
+pub struct BookLedger {
+    entries: StaticStorageRef<u64, BookEntry>,
+    balances: StaticStorageRef<String, BookBalance>,
+    block_entries: StaticStorageRef<u64, u64>,
+}
+
+impl BookLedger {
+    pub fn new(
+        entries: StaticStorageRef<u64, BookEntry>,
+        balances: StaticStorageRef<String, BookBalance>,
+        block_entries: StaticStorageRef<u64, u64>,
+    ) -> Self {
+        Self {
+            entries,
+            balances,
+            block_entries,
+        }
+    }
+
+    /// Validate and post an entry, the balances of all its accounts are updated in the same message
+    /// # Returns
+    /// * `Result<(u64, BookEntry), ApiError>` - The entry id and the entry
+    pub fn post(&self, entry: BookEntry) -> CanisterResult<(u64, BookEntry)> {
+        entry.validate()?;
+
+        for line in entry.lines.iter() {
+            self.balances.with(|data| {
+                let key = line.account.key();
+                let mut balance = data.borrow().get(&key).unwrap_or_default();
+                balance.debits_e8s += line.debit_e8s as u128;
+                balance.credits_e8s += line.credit_e8s as u128;
+                data.borrow_mut().insert(key, balance);
+            });
+        }
+
+        self.entries.with(|data| {
+            let id = data
+                .borrow()
+                .last_key_value()
+                .map(|(k, _)| k + 1)
+                .unwrap_or(1);
+            data.borrow_mut().insert(id, entry.clone());
+
+            if let Some(block_index) = entry.block_index {
+                self.block_entries.with(|blocks| {
+                    if !blocks.borrow().contains_key(&block_index) {
+                        blocks.borrow_mut().insert(block_index, id);
+                    }
+                });
+            }
+            Ok((id, entry))
+        })
+    }
+
+    /// Record ICP received from a principal, e.g. a verified payment or a sweep
+    /// # Returns
+    /// * `Result<(u64, BookEntry), ApiError>` - A `Duplicate` error when the block is already linked to an entry
+    pub fn record_deposit(
+        &self,
+        principal: Principal,
+        amount_e8s: u64,
+        block_index: Option<u64>,
+    ) -> CanisterResult<(u64, BookEntry)> {
+        if let Some(block_index) = block_index {
+            if let Some(id) = self
+                .block_entries
+                .with(|data| data.borrow().get(&block_index))
+            {
+                return Err(ApiError::duplicate(&format!(
+                    "Block {} is already recorded in entry {}",
+                    block_index, id
+                ))
+                .add_method_name("record_deposit")
+                .add_info(principal)
+                .add_source("toolkit_utils"));
+            }
+        }
+
+        self.post(BookEntry::new(
+            vec![
+                BookLine::debit(BookAccount::Treasury, amount_e8s),
+                BookLine::credit(BookAccount::User(principal), amount_e8s),
+            ],
+            "deposit",
+            block_index,
+            msg_caller(),
+        ))
+    }
+
+    /// Spend from the credit of a principal, e.g. on `REVENUE_PROJECT_FEES`
+    /// # Returns
+    /// * `Result<(u64, BookEntry), ApiError>` - A `BadRequest` error when the credit is insufficient
+    pub fn record_spend(
+        &self,
+        principal: Principal,
+        amount_e8s: u64,
+        revenue: &str,
+        block_index: Option<u64>,
+    ) -> CanisterResult<(u64, BookEntry)> {
+        self.ensure_credit(principal, amount_e8s, "record_spend")?;
+        self.post(BookEntry::new(
+            vec![
+                BookLine::debit(BookAccount::User(principal), amount_e8s),
+                BookLine::credit(BookAccount::Revenue(revenue.to_string()), amount_e8s),
+            ],
+            "spend",
+            block_index,
+            msg_caller(),
+        ))
+    }
+
+    /// Record ICP paid back to a principal
+    pub fn record_refund(
+        &self,
+        principal: Principal,
+        amount_e8s: u64,
+        block_index: Option<u64>,
+    ) -> CanisterResult<(u64, BookEntry)> {
+        self.ensure_credit(principal, amount_e8s, "record_refund")?;
+        self.post(BookEntry::new(
+            vec![
+                BookLine::debit(BookAccount::User(principal), amount_e8s),
+                BookLine::credit(BookAccount::Treasury, amount_e8s),
+            ],
+            "refund",
+            block_index,
+            msg_caller(),
+        ))
+    }
+
+    pub fn get_balance(&self, account: &BookAccount) -> BookBalance {
+        self.balances
+            .with(|data| data.borrow().get(&account.key()))
+            .unwrap_or_default()
+    }
+
+    /// The credit of a principal
+    pub fn get_user_balance(&self, principal: Principal) -> u128 {
+        self.get_balance(&BookAccount::User(principal))
+            .credit_balance()
+            .max(0) as u128
+    }
+
+    pub fn get_entry(&self, id: u64) -> CanisterResult<BookEntry> {
+        self.entries
+            .with(|data| data.borrow().get(&id))
+            .ok_or_else(|| {
+                ApiError::not_found("Entry not found")
+                    .add_method_name("get_entry")
+                    .add_info(id)
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    pub fn get_entries_by_account(&self, account: &BookAccount) -> Vec<(u64, BookEntry)> {
+        self.entries.with(|data| {
+            data.borrow()
+                .iter()
+                .filter(|(_, entry)| entry.lines.iter().any(|line| &line.account == account))
+                .collect()
+        })
+    }
+
+    pub fn get_entries_by_block_index(&self, block_index: u64) -> Vec<(u64, BookEntry)> {
+        self.entries.with(|data| {
+            data.borrow()
+                .iter()
+                .filter(|(_, entry)| entry.block_index == Some(block_index))
+                .collect()
+        })
+    }
+
+    /// Replay all entries and verify that every entry is balanced, total debits equal total credits
+    /// and the stored balances match the replayed balances
+    pub fn check_invariants(&self) -> CanisterResult<BookInvariantReport> {
+        let mut replayed = std::collections::BTreeMap::<String, BookBalance>::new();
+        let mut report = BookInvariantReport {
+            entries: 0,
+            total_debits_e8s: 0,
+            total_credits_e8s: 0,
+        };
+
+        let entries: Vec<(u64, BookEntry)> =
+            self.entries.with(|data| data.borrow().iter().collect());
+
+        for (id, entry) in entries {
+            entry.validate().map_err(|err| {
+                err.with_context(format!("Entry {} is not balanced", id))
+                    .add_method_name("check_invariants")
+            })?;
+
+            for line in entry.lines.iter() {
+                let balance = replayed.entry(line.account.key()).or_default();
+                balance.debits_e8s += line.debit_e8s as u128;
+                balance.credits_e8s += line.credit_e8s as u128;
+            }
+
+            report.entries += 1;
+            report.total_debits_e8s += entry.total_debits();
+            report.total_credits_e8s += entry.total_credits();
+        }
+
+        let violation = |message: String| {
+            Err(ApiError::unexpected(&message)
+                .add_method_name("check_invariants")
+                .add_source("toolkit_utils"))
+        };
+
+        if report.total_debits_e8s != report.total_credits_e8s {
+            return violation("Total debits do not equal total credits".to_string());
+        }
+
+        let stored: Vec<(String, BookBalance)> =
+            self.balances.with(|data| data.borrow().iter().collect());
+
+        if stored.len() != replayed.len() {
+            return violation("Stored accounts do not match the entries".to_string());
+        }
+
+        for (key, balance) in stored {
+            if replayed.get(&key) != Some(&balance) {
+                return violation(format!(
+                    "Stored balance of {} does not match the entries",
+                    key
+                ));
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn ensure_credit(
+        &self,
+        principal: Principal,
+        amount_e8s: u64,
+        method_name: &str,
+    ) -> CanisterResult<()> {
+        if self.get_user_balance(principal) < amount_e8s as u128 {
+            return Err(ApiError::bad_request("Insufficient balance")
+                .add_method_name(method_name)
+                .add_info(principal)
+                .add_source("toolkit_utils"));
+        }
+        Ok(())
+    }
+}
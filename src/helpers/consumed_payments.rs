@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+
+use ic_ledger_types::BlockIndex;
+
+use crate::{
+    api_error::ApiError, consumed_payment::ConsumedPayment, result::CanisterResult,
+    StaticStorageRef,
+};
+
+thread_local! {
+    static CONSUMED_PAYMENTS_STORAGE: RefCell<Option<StaticStorageRef<BlockIndex, ConsumedPayment>>> = const { RefCell::new(None) };
+}
+
+/// Register the map that holds the consumed payment blocks, call this in `init` and `post_upgrade`.
+///
+/// `verify_payment` refuses to verify payments when no map is registered.
+pub fn register_consumed_payments_storage(storage: StaticStorageRef<BlockIndex, ConsumedPayment>) {
+    CONSUMED_PAYMENTS_STORAGE.with(|current| *current.borrow_mut() = Some(storage));
+}
+
+pub fn is_payment_consumed(block_index: BlockIndex) -> CanisterResult<bool> {
+    Ok(storage("is_payment_consumed")?.with(|data| data.borrow().contains_key(&block_index)))
+}
+
+pub fn get_consumed_payment(block_index: BlockIndex) -> CanisterResult<ConsumedPayment> {
+    storage("get_consumed_payment")?
+        .with(|data| data.borrow().get(&block_index))
+        .ok_or_else(|| {
+            ApiError::not_found("Payment not consumed")
+                .add_method_name("get_consumed_payment")
+                .add_info(block_index)
+                .add_source("toolkit_utils")
+        })
+}
+
+/// Mark a payment as consumed
+/// # Returns
+/// * `Result<ConsumedPayment, ApiError>` - The consumed payment, or a duplicate error if the block was already consumed
+pub fn consume_payment(payment: ConsumedPayment) -> CanisterResult<ConsumedPayment> {
+    storage("consume_payment")?.with(|data| {
+        if data.borrow().contains_key(&payment.block_index) {
+            return Err(ApiError::duplicate("Payment already consumed")
+                .add_method_name("consume_payment")
+                .add_info(payment.block_index)
+                .add_source("toolkit_utils"));
+        }
+
+        data.borrow_mut()
+            .insert(payment.block_index, payment.clone());
+        Ok(payment)
+    })
+}
+
+fn storage(method_name: &str) -> CanisterResult<StaticStorageRef<BlockIndex, ConsumedPayment>> {
+    CONSUMED_PAYMENTS_STORAGE
+        .with(|current| *current.borrow())
+        .ok_or_else(|| {
+            ApiError::unexpected("Consumed payments storage is not registered")
+                .add_method_name(method_name)
+                .add_source("toolkit_utils")
+        })
+}
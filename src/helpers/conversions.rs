@@ -0,0 +1,58 @@
+use candid::Nat;
+use ic_ledger_types::Tokens;
+
+use crate::{api_error::ApiError, result::CanisterResult};
+
+/// Convert a `Nat` to `u64`
+/// # Returns
+/// * `Result<u64, ApiError>` - A `BadRequest` error when the value does not fit in `u64`
+pub fn try_nat_to_u64(value: &Nat) -> CanisterResult<u64> {
+    value
+        .0
+        .clone()
+        .try_into()
+        .map_err(|_| overflow(value, "u64", "try_nat_to_u64"))
+}
+
+/// Convert a `Nat` to `u128`
+/// # Returns
+/// * `Result<u128, ApiError>` - A `BadRequest` error when the value does not fit in `u128`
+pub fn try_nat_to_u128(value: &Nat) -> CanisterResult<u128> {
+    value
+        .0
+        .clone()
+        .try_into()
+        .map_err(|_| overflow(value, "u128", "try_nat_to_u128"))
+}
+
+/// Convert a `u128` to `u64`
+pub fn try_u128_to_u64(value: u128) -> CanisterResult<u64> {
+    value
+        .try_into()
+        .map_err(|_| overflow(value, "u64", "try_u128_to_u64"))
+}
+
+/// Convert a `Nat` amount in e8s to `Tokens`
+pub fn try_nat_to_tokens(value: &Nat) -> CanisterResult<Tokens> {
+    try_nat_to_u64(value)
+        .map(Tokens::from_e8s)
+        .map_err(|err| err.add_method_name("try_nat_to_tokens"))
+}
+
+pub fn tokens_to_nat(tokens: Tokens) -> Nat {
+    Nat::from(tokens.e8s())
+}
+
+pub fn u64_to_nat(value: u64) -> Nat {
+    Nat::from(value)
+}
+
+pub fn u128_to_nat(value: u128) -> Nat {
+    Nat::from(value)
+}
+
+fn overflow<V: std::fmt::Display>(value: V, target: &str, method_name: &str) -> Box<ApiError> {
+    ApiError::bad_request(&format!("Value {} does not fit in {}", value, target))
+        .add_method_name(method_name)
+        .add_source("toolkit_utils")
+}
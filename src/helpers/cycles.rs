@@ -1,79 +1,332 @@
-use candid::Nat;
-use ic_ledger_types::MAINNET_CYCLES_MINTING_CANISTER_ID;
+use candid::{Nat, Principal};
+use ic_ledger_types::{
+    transfer, AccountIdentifier, Memo, Subaccount, Timestamp, Tokens, TransferArgs,
+};
+use icrc_ledger_types::icrc1::account::Account;
 
 use crate::{
     api_error::ApiError,
-    cycles_minting::CyclesMintingService,
-    misc::generic::{ICP_E8S, TRILLION_CYCLES},
+    cycles_ledger::{
+        BlockIndex, CmcCreateCanisterArgs, CreateCanisterArgs, CreateCanisterSuccess,
+        CyclesLedgerService, WithdrawArgs,
+    },
+    cycles_minting::{
+        CanisterSettings, CyclesMintingService, NotifyMintCyclesArg, NotifyMintCyclesResult,
+        NotifyMintCyclesSuccess, SubnetSelection,
+    },
+    misc::{
+        generic::{ICP_E8S, MEMO_MINT_CYCLES},
+        runtime::canister_self,
+    },
+    network_config::NetworkConfig,
     result::CanisterResult,
+    token_amount::{RoundingMode, TokenAmount, CYCLES_DECIMALS, ICP_DECIMALS},
+    token_ledger::TokenLedger,
+    transfer_plan::FeePayer,
 };
 
-use super::misc::{e12s_to_f64, e8s_to_f64, f64_to_e8s, nat_to_u64};
+use super::{
+    network::network_config, rate_provider::conversion_rate_on, transactions::plan_icp_transfer_on,
+};
 
 pub async fn cycles_per_icp() -> CanisterResult<Nat> {
-    let cycles_minting_service = CyclesMintingService(MAINNET_CYCLES_MINTING_CANISTER_ID);
-    let result = cycles_minting_service
-        .get_icp_xdr_conversion_rate()
+    cycles_per_icp_on(&network_config()).await
+}
+
+pub async fn cycles_per_icp_on(network: &NetworkConfig) -> CanisterResult<Nat> {
+    let xdr_permyriad_per_icp = xdr_permyriad_per_icp_on(network)
         .await
-        .map(|(rate,)| rate)
-        .map_err(|_| {
-            ApiError::external_service_error("Error getting XDR conversion rate")
-                .add_method_name("cycles_per_icp")
-                .add_info("toolkit_utils")
-        })?;
+        .map_err(|err| err.add_method_name("cycles_per_icp"))?;
 
-    Ok(Nat::from(
-        (result.data.xdr_permyriad_per_icp * TRILLION_CYCLES) / 10_000,
-    ))
+    TokenAmount::from_e8s(ICP_E8S)
+        .icp_to_cycles(xdr_permyriad_per_icp)
+        .map(|cycles| cycles.to_nat())
 }
 
 pub async fn xdr_permyriad_per_icp() -> CanisterResult<u64> {
-    let cycles_minting_service = CyclesMintingService(MAINNET_CYCLES_MINTING_CANISTER_ID);
-    let result = cycles_minting_service
-        .get_icp_xdr_conversion_rate()
-        .await
-        .map(|(rate,)| rate)
-        .map_err(|_| {
-            ApiError::external_service_error("Error getting XDR conversion rate")
-                .add_method_name("xdr_permyriad_per_icp")
-                .add_info("toolkit_utils")
-        })?;
+    xdr_permyriad_per_icp_on(&network_config()).await
+}
 
-    Ok(result.data.xdr_permyriad_per_icp)
+pub async fn xdr_permyriad_per_icp_on(network: &NetworkConfig) -> CanisterResult<u64> {
+    conversion_rate_on(network)
+        .await
+        .map(|rate| rate.xdr_permyriad_per_icp)
+        .map_err(|err| err.add_method_name("xdr_permyriad_per_icp"))
 }
 
 pub async fn calculate_icp_fee_in_e8s(xdr_fee: u64) -> CanisterResult<u64> {
-    // Step 1: Retrieve the XDR conversion rate (XDR per ICP).
-    let cycles_minting_service = CyclesMintingService(MAINNET_CYCLES_MINTING_CANISTER_ID);
-    let result = cycles_minting_service
-        .get_icp_xdr_conversion_rate()
-        .await
-        .map(|(rate,)| rate)
-        .map_err(|_| {
-            ApiError::external_service_error("Error getting XDR conversion rate")
-                .add_method_name("calculate_icp_fee_in_e8s")
-                .add_info("toolkit_utils")
-        })?;
+    calculate_icp_fee_in_e8s_on(&network_config(), xdr_fee).await
+}
 
-    // Step 2: Calculate ICP fee in e8s from XDR.
-    let xdr_per_icp = result.data.xdr_permyriad_per_icp; // typically around 10,000 for 1 XDR.
-    let icp_fee_e8s = (xdr_fee * ICP_E8S) / xdr_per_icp; // Converts fee to ICP in e8s
+pub async fn calculate_icp_fee_in_e8s_on(
+    network: &NetworkConfig,
+    xdr_fee: u64,
+) -> CanisterResult<u64> {
+    let xdr_permyriad_per_icp = xdr_permyriad_per_icp_on(network)
+        .await
+        .map_err(|err| err.add_method_name("calculate_icp_fee_in_e8s"))?;
 
-    Ok(icp_fee_e8s)
+    // `xdr_fee` is in XDR permyriad, e.g. 5_000 for 0.5 XDR
+    TokenAmount::from_xdr_permyriad(xdr_fee)
+        .xdr_to_icp(xdr_permyriad_per_icp, RoundingMode::Down)?
+        .to_u64()
 }
 
 pub async fn cycles_per_icp_e8s(e8s: Nat) -> CanisterResult<u64> {
-    let cycles_per_icp = e8s_to_f64(&cycles_per_icp().await?);
-    let tokens_e8s = e8s_to_f64(&e8s);
+    cycles_per_icp_e8s_on(&network_config(), e8s).await
+}
 
-    let cycles = (tokens_e8s) * cycles_per_icp;
-    Ok(nat_to_u64(&f64_to_e8s(cycles)))
+pub async fn cycles_per_icp_e8s_on(network: &NetworkConfig, e8s: Nat) -> CanisterResult<u64> {
+    let xdr_permyriad_per_icp = xdr_permyriad_per_icp_on(network).await?;
+
+    TokenAmount::from_nat(&e8s, ICP_DECIMALS)?
+        .icp_to_cycles(xdr_permyriad_per_icp)?
+        .to_u64()
 }
 
 pub async fn icp_per_cycles_e12s(e12s: Nat) -> CanisterResult<Nat> {
-    let cycles_per_icp = e8s_to_f64(&cycles_per_icp().await?);
-    let tokens_e12s = e12s_to_f64(&e12s);
+    icp_per_cycles_e12s_on(&network_config(), e12s).await
+}
+
+pub async fn icp_per_cycles_e12s_on(network: &NetworkConfig, e12s: Nat) -> CanisterResult<Nat> {
+    let xdr_permyriad_per_icp = xdr_permyriad_per_icp_on(network).await?;
+
+    TokenAmount::from_nat(&e12s, CYCLES_DECIMALS)?
+        .cycles_to_icp(xdr_permyriad_per_icp, RoundingMode::Down)
+        .map(|icp| icp.to_nat())
+}
+
+////////////////////////////////////////////////////////////
+// CYCLES LEDGER
+////////////////////////////////////////////////////////////
+
+/// Convert ICP of this canister into cycles on the cycles ledger, credited to `to_subaccount` of this canister
+///
+/// Pass the same `created_at_time` when retrying after an unknown outcome, so the ledger deduplicates the transfer.
+/// # Returns
+/// * `Result<(u64, Result<NotifyMintCyclesSuccess, ApiError>), ApiError>` - The block of the ICP transfer and
+///   the result of the notify, a failed notify can be retried with `notify_mint_cycles` and the block
+pub async fn mint_cycles_to_cycles_ledger(
+    icp_amount: u64,
+    to_subaccount: Option<[u8; 32]>,
+    created_at_time: Option<u64>,
+) -> CanisterResult<(u64, CanisterResult<NotifyMintCyclesSuccess>)> {
+    mint_cycles_to_cycles_ledger_on(
+        &network_config(),
+        icp_amount,
+        to_subaccount,
+        created_at_time,
+    )
+    .await
+}
+
+pub async fn mint_cycles_to_cycles_ledger_on(
+    network: &NetworkConfig,
+    icp_amount: u64,
+    to_subaccount: Option<[u8; 32]>,
+    created_at_time: Option<u64>,
+) -> CanisterResult<(u64, CanisterResult<NotifyMintCyclesSuccess>)> {
+    let block_index = transfer_icp_for_cycles_on(network, icp_amount, created_at_time)
+        .await
+        .map_err(|err| err.add_method_name("mint_cycles_to_cycles_ledger"))?;
+
+    let result = notify_mint_cycles_on(network, block_index, to_subaccount)
+        .await
+        .map_err(|err| err.add_method_name("mint_cycles_to_cycles_ledger"));
+
+    Ok((block_index, result))
+}
+
+/// Send ICP of this canister to the CMC with the `MEMO_MINT_CYCLES` memo, the first step of
+/// `mint_cycles_to_cycles_ledger`. Follow up with `notify_mint_cycles` and the returned block.
+pub async fn transfer_icp_for_cycles(
+    icp_amount: u64,
+    created_at_time: Option<u64>,
+) -> CanisterResult<u64> {
+    transfer_icp_for_cycles_on(&network_config(), icp_amount, created_at_time).await
+}
 
-    let icp = tokens_e12s / (cycles_per_icp / 10_000f64);
-    Ok(f64_to_e8s(icp))
+pub async fn transfer_icp_for_cycles_on(
+    network: &NetworkConfig,
+    icp_amount: u64,
+    created_at_time: Option<u64>,
+) -> CanisterResult<u64> {
+    let plan = plan_icp_transfer_on(network, icp_amount, FeePayer::Recipient, 1).await?;
+
+    // the CMC credits the cycles to the principal that matches the subaccount and calls notify
+    let args = TransferArgs {
+        memo: Memo(MEMO_MINT_CYCLES),
+        amount: Tokens::from_e8s(plan.net_e8s),
+        fee: Tokens::from_e8s(plan.fee_e8s),
+        from_subaccount: None,
+        to: AccountIdentifier::new(
+            &network.cycles_minting_canister_id,
+            &Subaccount::from(canister_self()),
+        ),
+        created_at_time: created_at_time.map(|timestamp_nanos| Timestamp { timestamp_nanos }),
+    };
+
+    match transfer(network.ledger_canister_id, &args).await {
+        Ok(Ok(block_index)) => Ok(block_index),
+        Ok(Err(err)) => Err(ApiError::from(err)
+            .add_method_name("transfer_icp_for_cycles")
+            .add_source("toolkit_utils")),
+        Err(err) => Err(ApiError::from(err)
+            .add_method_name("transfer_icp_for_cycles")
+            .add_source("toolkit_utils")),
+    }
+}
+
+/// Notify the CMC about an ICP transfer with the `MEMO_MINT_CYCLES` memo, this is safe to retry
+pub async fn notify_mint_cycles(
+    block_index: u64,
+    to_subaccount: Option<[u8; 32]>,
+) -> CanisterResult<NotifyMintCyclesSuccess> {
+    notify_mint_cycles_on(&network_config(), block_index, to_subaccount).await
+}
+
+pub async fn notify_mint_cycles_on(
+    network: &NetworkConfig,
+    block_index: u64,
+    to_subaccount: Option<[u8; 32]>,
+) -> CanisterResult<NotifyMintCyclesSuccess> {
+    match CyclesMintingService(network.cycles_minting_canister_id)
+        .notify_mint_cycles(NotifyMintCyclesArg {
+            block_index,
+            deposit_memo: None,
+            to_subaccount: to_subaccount.map(|subaccount| subaccount.to_vec()),
+        })
+        .await
+    {
+        Ok((NotifyMintCyclesResult::Ok(result),)) => Ok(result),
+        Ok((NotifyMintCyclesResult::Err(err),)) => Err(ApiError::from(err)
+            .add_method_name("notify_mint_cycles")
+            .add_source("toolkit_utils")),
+        Err(err) => Err(ApiError::from(err)
+            .add_method_name("notify_mint_cycles")
+            .add_source("toolkit_utils")),
+    }
+}
+
+pub async fn get_cycles_ledger_balance(account: Account) -> CanisterResult<Nat> {
+    get_cycles_ledger_balance_on(&network_config(), account).await
+}
+
+pub async fn get_cycles_ledger_balance_on(
+    network: &NetworkConfig,
+    account: Account,
+) -> CanisterResult<Nat> {
+    TokenLedger::new(network.cycles_ledger_canister_id)
+        .icrc1_balance_of(account)
+        .await
+}
+
+/// Transfer cycles on the cycles ledger from a subaccount of this canister, e.g. to fund a user
+///
+/// Pass the same `created_at_time` when retrying after an unknown outcome, so the ledger deduplicates the transfer.
+pub async fn transfer_cycles(
+    from_subaccount: Option<[u8; 32]>,
+    to: Account,
+    cycles: Nat,
+    created_at_time: Option<u64>,
+) -> CanisterResult<BlockIndex> {
+    transfer_cycles_on(
+        &network_config(),
+        from_subaccount,
+        to,
+        cycles,
+        created_at_time,
+    )
+    .await
+}
+
+pub async fn transfer_cycles_on(
+    network: &NetworkConfig,
+    from_subaccount: Option<[u8; 32]>,
+    to: Account,
+    cycles: Nat,
+    created_at_time: Option<u64>,
+) -> CanisterResult<BlockIndex> {
+    TokenLedger::new(network.cycles_ledger_canister_id)
+        .transfer(from_subaccount, to, cycles, None, created_at_time)
+        .await
+}
+
+/// Withdraw cycles from the cycles ledger account of this canister to a canister
+///
+/// Pass the same `created_at_time` when retrying after an unknown outcome, so the ledger deduplicates the withdrawal.
+pub async fn withdraw_cycles_to_canister(
+    from_subaccount: Option<[u8; 32]>,
+    cycles: Nat,
+    canister_id: Principal,
+    created_at_time: Option<u64>,
+) -> CanisterResult<BlockIndex> {
+    withdraw_cycles_to_canister_on(
+        &network_config(),
+        from_subaccount,
+        cycles,
+        canister_id,
+        created_at_time,
+    )
+    .await
+}
+
+pub async fn withdraw_cycles_to_canister_on(
+    network: &NetworkConfig,
+    from_subaccount: Option<[u8; 32]>,
+    cycles: Nat,
+    canister_id: Principal,
+    created_at_time: Option<u64>,
+) -> CanisterResult<BlockIndex> {
+    CyclesLedgerService(network.cycles_ledger_canister_id)
+        .withdraw(WithdrawArgs {
+            amount: cycles,
+            from_subaccount: from_subaccount.map(|subaccount| subaccount.to_vec()),
+            to: canister_id,
+            created_at_time,
+        })
+        .await
+}
+
+/// Create a canister paid with cycles from the cycles ledger account of this canister
+///
+/// Pass the same `created_at_time` when retrying after an unknown outcome, so the ledger
+/// returns the canister of the first attempt instead of creating another one.
+pub async fn create_canister_from_cycles_ledger(
+    from_subaccount: Option<[u8; 32]>,
+    cycles: Nat,
+    settings: Option<CanisterSettings>,
+    subnet_selection: Option<SubnetSelection>,
+    created_at_time: Option<u64>,
+) -> CanisterResult<CreateCanisterSuccess> {
+    create_canister_from_cycles_ledger_on(
+        &network_config(),
+        from_subaccount,
+        cycles,
+        settings,
+        subnet_selection,
+        created_at_time,
+    )
+    .await
+}
+
+pub async fn create_canister_from_cycles_ledger_on(
+    network: &NetworkConfig,
+    from_subaccount: Option<[u8; 32]>,
+    cycles: Nat,
+    settings: Option<CanisterSettings>,
+    subnet_selection: Option<SubnetSelection>,
+    created_at_time: Option<u64>,
+) -> CanisterResult<CreateCanisterSuccess> {
+    CyclesLedgerService(network.cycles_ledger_canister_id)
+        .create_canister(CreateCanisterArgs {
+            from_subaccount: from_subaccount.map(|subaccount| subaccount.to_vec()),
+            created_at_time,
+            amount: cycles,
+            creation_args: Some(CmcCreateCanisterArgs {
+                settings,
+                subnet_selection,
+            }),
+        })
+        .await
 }
@@ -0,0 +1,233 @@
+use std::ops::RangeInclusive;
+
+use candid::Principal;
+use ic_cdk::management_canister::{canister_status, CanisterStatusArgs};
+
+use crate::{
+    api_error::ApiError, cycles_snapshot::CyclesSnapshot, misc::runtime::time,
+    result::CanisterResult, runway_forecast::RunwayForecast, StaticStorageRef,
+};
+
+pub static DEFAULT_RAW_RETENTION_SECONDS: u64 = 60 * 60 * 24; // 1 day
+pub static DEFAULT_HOURLY_RETENTION_SECONDS: u64 = 60 * 60 * 24 * 30; // 30 days
+pub static DEFAULT_MAX_SNAPSHOT_AGE_SECONDS: u64 = 60 * 60 * 24 * 365; // 1 year
+pub static DEFAULT_RUNWAY_WINDOW_SECONDS: u64 = 60 * 60 * 24 * 7; // 7 days
+
+static NANOS_PER_SECOND: u64 = 1_000_000_000;
+static SECONDS_PER_HOUR: u64 = 60 * 60;
+static SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+
+/// Stable time series of `canister_status` snapshots per canister.
+///
+/// Recent snapshots are kept as taken, older ones are downsampled to the last snapshot per hour
+/// and then per day, snapshots older than the max age are removed.
+#[derive(Clone, Copy)]
+pub struct CyclesHistory {
+    snapshots: StaticStorageRef<(Principal, u64), CyclesSnapshot>,
+    raw_retention_seconds: u64,
+    hourly_retention_seconds: u64,
+    max_age_seconds: u64,
+}
+
+impl CyclesHistory {
+    pub fn new(snapshots: StaticStorageRef<(Principal, u64), CyclesSnapshot>) -> Self {
+        Self {
+            snapshots,
+            raw_retention_seconds: DEFAULT_RAW_RETENTION_SECONDS,
+            hourly_retention_seconds: DEFAULT_HOURLY_RETENTION_SECONDS,
+            max_age_seconds: DEFAULT_MAX_SNAPSHOT_AGE_SECONDS,
+        }
+    }
+
+    pub fn with_retention(
+        mut self,
+        raw_retention_seconds: u64,
+        hourly_retention_seconds: u64,
+        max_age_seconds: u64,
+    ) -> Self {
+        self.raw_retention_seconds = raw_retention_seconds;
+        self.hourly_retention_seconds = hourly_retention_seconds.max(raw_retention_seconds);
+        self.max_age_seconds = max_age_seconds.max(self.hourly_retention_seconds);
+        self
+    }
+
+    /// Fetch the status of a canister and store it as a snapshot
+    pub async fn record_snapshot(&self, canister_id: Principal) -> CanisterResult<CyclesSnapshot> {
+        let status = canister_status(&CanisterStatusArgs { canister_id })
+            .await
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name("record_snapshot")
+                    .add_info(canister_id)
+                    .add_source("toolkit_utils")
+            })?;
+
+        let snapshot = CyclesSnapshot::from_status(&status)?;
+        self.insert(canister_id, snapshot.clone());
+        Ok(snapshot)
+    }
+
+    /// Store a snapshot that was taken elsewhere and downsample the history of the canister
+    pub fn insert(&self, canister_id: Principal, snapshot: CyclesSnapshot) {
+        self.snapshots.with(|data| {
+            data.borrow_mut()
+                .insert((canister_id, snapshot.taken_at), snapshot)
+        });
+        self.downsample(canister_id);
+    }
+
+    pub fn get_snapshots(&self, canister_id: Principal) -> Vec<CyclesSnapshot> {
+        self.snapshots_between(canister_id, 0..=u64::MAX)
+    }
+
+    pub fn get_latest_snapshot(&self, canister_id: Principal) -> Option<CyclesSnapshot> {
+        self.snapshots.with(|data| {
+            data.borrow()
+                .range(Self::key_range(canister_id, 0..=u64::MAX))
+                .next_back()
+                .map(|(_, snapshot)| snapshot)
+        })
+    }
+
+    /// Remove all snapshots of a canister
+    pub fn clear(&self, canister_id: Principal) {
+        self.snapshots.with(|data| {
+            let keys: Vec<(Principal, u64)> = data
+                .borrow()
+                .range(Self::key_range(canister_id, 0..=u64::MAX))
+                .map(|(key, _)| key)
+                .collect();
+            for key in keys {
+                data.borrow_mut().remove(&key);
+            }
+        });
+    }
+
+    /// Keep the last snapshot per hour / day once snapshots pass the raw / hourly retention
+    pub fn downsample(&self, canister_id: Principal) {
+        let now = time();
+        let raw_after = now.saturating_sub(self.raw_retention_seconds * NANOS_PER_SECOND);
+        let hourly_after = now.saturating_sub(self.hourly_retention_seconds * NANOS_PER_SECOND);
+        let expired_before = now.saturating_sub(self.max_age_seconds * NANOS_PER_SECOND);
+
+        self.snapshots.with(|data| {
+            let mut remove = vec![];
+            let mut previous: Option<((Principal, u64), u64)> = None;
+
+            for (key, _) in data
+                .borrow()
+                .range(Self::key_range(canister_id, 0..=raw_after))
+            {
+                let taken_at = key.1;
+                if taken_at < expired_before {
+                    remove.push(key);
+                    continue;
+                }
+
+                let bucket_seconds = if taken_at < hourly_after {
+                    SECONDS_PER_DAY
+                } else {
+                    SECONDS_PER_HOUR
+                };
+                let bucket = taken_at / (bucket_seconds * NANOS_PER_SECOND);
+
+                // the snapshots are ordered by time, so only the last one of a bucket is kept
+                if let Some((previous_key, previous_bucket)) = previous {
+                    if previous_bucket == bucket {
+                        remove.push(previous_key);
+                    }
+                }
+                previous = Some((key, bucket));
+            }
+
+            for key in remove {
+                data.borrow_mut().remove(&key);
+            }
+        });
+    }
+
+    /// Project the runway of a canister from the burn observed within the last `window_seconds`.
+    ///
+    /// Balance increases (top-ups) between snapshots are ignored, the burn rate is at least the idle burn.
+    pub fn forecast(
+        &self,
+        canister_id: Principal,
+        window_seconds: u64,
+    ) -> CanisterResult<RunwayForecast> {
+        let latest = self.get_latest_snapshot(canister_id).ok_or_else(|| {
+            ApiError::not_found("No snapshots for canister")
+                .add_method_name("forecast")
+                .add_info(canister_id)
+                .add_source("toolkit_utils")
+        })?;
+
+        let window_start = latest
+            .taken_at
+            .saturating_sub(window_seconds.saturating_mul(NANOS_PER_SECOND));
+        let snapshots = self.snapshots_between(canister_id, window_start..=latest.taken_at);
+
+        let burned: u128 = snapshots
+            .windows(2)
+            .map(|pair| pair[0].cycles.saturating_sub(pair[1].cycles))
+            .sum();
+        let elapsed_seconds = snapshots
+            .first()
+            .map(|first| (latest.taken_at - first.taken_at) / NANOS_PER_SECOND)
+            .unwrap_or_default();
+
+        let observed_per_day = match elapsed_seconds {
+            0 => 0,
+            elapsed => burned.saturating_mul(u128::from(SECONDS_PER_DAY)) / u128::from(elapsed),
+        };
+        let burn_rate_cycles_per_day = observed_per_day.max(latest.idle_cycles_burned_per_day);
+
+        let freezing_threshold_cycles = latest.freezing_threshold_cycles();
+        let runway_seconds = match burn_rate_cycles_per_day {
+            0 => None,
+            burn => {
+                let spendable = latest.cycles.saturating_sub(freezing_threshold_cycles);
+                let seconds = spendable.saturating_mul(u128::from(SECONDS_PER_DAY)) / burn;
+                Some(u64::try_from(seconds).unwrap_or(u64::MAX))
+            }
+        };
+
+        Ok(RunwayForecast {
+            canister_id,
+            cycles: latest.cycles,
+            freezing_threshold_cycles,
+            burn_rate_cycles_per_day,
+            idle_cycles_burned_per_day: latest.idle_cycles_burned_per_day,
+            memory_size: latest.memory_size,
+            runway_seconds,
+            runway_days: runway_seconds.map(|seconds| seconds / SECONDS_PER_DAY),
+            freezes_at: runway_seconds.map(|seconds| {
+                latest
+                    .taken_at
+                    .saturating_add(seconds.saturating_mul(NANOS_PER_SECOND))
+            }),
+            samples: snapshots.len() as u64,
+            window_seconds,
+            forecast_at: time(),
+        })
+    }
+
+    fn snapshots_between(
+        &self,
+        canister_id: Principal,
+        range: RangeInclusive<u64>,
+    ) -> Vec<CyclesSnapshot> {
+        self.snapshots.with(|data| {
+            data.borrow()
+                .range(Self::key_range(canister_id, range))
+                .map(|(_, snapshot)| snapshot)
+                .collect()
+        })
+    }
+
+    fn key_range(
+        canister_id: Principal,
+        range: RangeInclusive<u64>,
+    ) -> RangeInclusive<(Principal, u64)> {
+        (canister_id, *range.start())..=(canister_id, *range.end())
+    }
+}
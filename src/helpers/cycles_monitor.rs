@@ -0,0 +1,417 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeSet,
+};
+
+use candid::{Nat, Principal};
+use ic_cdk::management_canister::{
+    canister_status, deposit_cycles, CanisterStatusArgs, DepositCyclesArgs,
+};
+
+use crate::{
+    api_error::{ApiError, ApiErrorType},
+    cycles_minting::NotifyError,
+    cycles_monitor_event::{CyclesMonitorAction, CyclesMonitorEvent},
+    cycles_snapshot::CyclesSnapshot,
+    governance_config::{
+        CANISTER_STATUS_FETCH_INTERVAL_LOWER_LIMIT_SECONDS,
+        CANISTER_STATUS_FETCH_INTERVAL_UPPER_LIMIT_SECONDS,
+    },
+    management_config::{ManagementConfig, DEFAULT_CANISTER_STATUS_FETCH_INTERVAL_SECONDS},
+    misc::{reentrancy::ReentrancyGuard, runtime::time},
+    monitored_canister::{MonitoredCanister, TopUpMethod},
+    network_config::NetworkConfig,
+    result::CanisterResult,
+    StaticStorageRef,
+};
+
+use super::{
+    cycles_history::CyclesHistory,
+    network::network_config,
+    transactions::{notify_top_up_cycles_external_canister_on, top_up_cycles_on},
+};
+
+static NANOS_PER_DAY: u128 = 24 * 60 * 60 * 1_000_000_000;
+
+thread_local! {
+    static MONITORING: Cell<bool> = const { Cell::new(false) };
+    static TOP_UP_LOCKS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+// held while a canister is topped up, so a manual top-up and a timer check never both send ICP
+struct TopUpLock(Principal);
+
+impl TopUpLock {
+    fn acquire(canister_id: Principal) -> Option<Self> {
+        TOP_UP_LOCKS
+            .with(|locks| locks.borrow_mut().insert(canister_id))
+            .then_some(Self(canister_id))
+    }
+}
+
+impl Drop for TopUpLock {
+    fn drop(&mut self) {
+        TOP_UP_LOCKS.with(|locks| locks.borrow_mut().remove(&self.0));
+    }
+}
+
+/// Checks the cycles balance of registered canisters with `canister_status` and tops them up
+/// when the balance drops below their threshold. Every status fetch and top-up is logged as an event.
+///
+/// Call `check_due` from a timer, only canisters that were not checked within the interval are fetched.
+/// The monitoring canister has to be a controller of the monitored canisters.
+#[derive(Clone, Copy)]
+pub struct CyclesMonitor {
+    canisters: StaticStorageRef<Principal, MonitoredCanister>,
+    events: StaticStorageRef<u64, CyclesMonitorEvent>,
+    history: Option<CyclesHistory>,
+    interval_seconds: u64,
+}
+
+impl CyclesMonitor {
+    pub fn new(
+        canisters: StaticStorageRef<Principal, MonitoredCanister>,
+        events: StaticStorageRef<u64, CyclesMonitorEvent>,
+    ) -> Self {
+        Self {
+            canisters,
+            events,
+            history: None,
+            interval_seconds: DEFAULT_CANISTER_STATUS_FETCH_INTERVAL_SECONDS,
+        }
+    }
+
+    /// The interval is clamped to the canister status fetch interval limits
+    pub fn with_interval_seconds(mut self, interval_seconds: u64) -> Self {
+        self.interval_seconds = interval_seconds.clamp(
+            CANISTER_STATUS_FETCH_INTERVAL_LOWER_LIMIT_SECONDS,
+            CANISTER_STATUS_FETCH_INTERVAL_UPPER_LIMIT_SECONDS,
+        );
+        self
+    }
+
+    /// Use the `canister_status_fetch_interval_seconds` of the management config
+    pub fn with_management_config(self, config: &ManagementConfig) -> Self {
+        self.with_interval_seconds(config.canister_status_fetch_interval_seconds)
+    }
+
+    /// Store every fetched status as a snapshot in the history, for runway forecasts
+    pub fn with_history(mut self, history: CyclesHistory) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub fn interval_seconds(&self) -> u64 {
+        self.interval_seconds
+    }
+
+    /// Register a canister or update the threshold and top-up method of a registered canister
+    pub fn register(
+        &self,
+        canister_id: Principal,
+        threshold_cycles: u128,
+        top_up: TopUpMethod,
+    ) -> CanisterResult<MonitoredCanister> {
+        let is_empty = match &top_up {
+            TopUpMethod::Icp { amount_e8s } => *amount_e8s == 0,
+            TopUpMethod::DepositCycles { cycles } => *cycles == 0,
+        };
+        if is_empty {
+            return Err(
+                ApiError::bad_request("Top-up amount must be greater than zero")
+                    .add_method_name("register")
+                    .add_info(canister_id)
+                    .add_source("toolkit_utils"),
+            );
+        }
+
+        self.canisters.with(|data| {
+            let canister = match data.borrow().get(&canister_id) {
+                Some(existing) => MonitoredCanister {
+                    threshold_cycles,
+                    top_up,
+                    ..existing
+                },
+                None => MonitoredCanister::new(threshold_cycles, top_up),
+            };
+            data.borrow_mut().insert(canister_id, canister.clone());
+            Ok(canister)
+        })
+    }
+
+    pub fn unregister(&self, canister_id: Principal) -> CanisterResult<MonitoredCanister> {
+        self.canisters
+            .with(|data| data.borrow_mut().remove(&canister_id))
+            .ok_or_else(|| Self::not_found(canister_id, "unregister"))
+    }
+
+    pub fn get(&self, canister_id: Principal) -> CanisterResult<MonitoredCanister> {
+        self.canisters
+            .with(|data| data.borrow().get(&canister_id))
+            .ok_or_else(|| Self::not_found(canister_id, "get"))
+    }
+
+    pub fn get_all(&self) -> Vec<(Principal, MonitoredCanister)> {
+        self.canisters.with(|data| data.borrow().iter().collect())
+    }
+
+    pub fn get_events(&self, canister_id: Principal) -> Vec<(u64, CyclesMonitorEvent)> {
+        self.events.with(|data| {
+            data.borrow()
+                .iter()
+                .filter(|(_, event)| event.canister_id == canister_id)
+                .collect()
+        })
+    }
+
+    /// Check all canisters that were not checked within the interval
+    /// # Returns
+    /// * `Vec<(Principal, Result<MonitoredCanister, ApiError>)>` - The updated state per checked canister
+    pub async fn check_due(&self) -> Vec<(Principal, CanisterResult<MonitoredCanister>)> {
+        self.check_due_on(&network_config()).await
+    }
+
+    pub async fn check_due_on(
+        &self,
+        network: &NetworkConfig,
+    ) -> Vec<(Principal, CanisterResult<MonitoredCanister>)> {
+        let Some(_guard) = ReentrancyGuard::acquire(&MONITORING) else {
+            return vec![];
+        };
+
+        let now = time();
+        let due: Vec<Principal> = self.canisters.with(|data| {
+            data.borrow()
+                .iter()
+                .filter(|(_, canister)| canister.is_due(self.interval_seconds, now))
+                .map(|(canister_id, _)| canister_id)
+                .collect()
+        });
+
+        let mut results = vec![];
+        for canister_id in due {
+            results.push((canister_id, self.check_on(network, canister_id).await));
+        }
+
+        results
+    }
+
+    /// Fetch the cycles balance of a canister, update the burn rate and top it up when below the threshold.
+    ///
+    /// A pending ICP top-up is notified again instead of sending new ICP, and a canister is topped up
+    /// at most once per interval. The record is read again after the status call, so changes made
+    /// in the meantime (a pending top-up, a new threshold or an unregister) are kept.
+    pub async fn check_on(
+        &self,
+        network: &NetworkConfig,
+        canister_id: Principal,
+    ) -> CanisterResult<MonitoredCanister> {
+        self.get(canister_id)?;
+        let result = self.fetch_cycles(canister_id).await;
+        let now = time();
+
+        let cycles = match result {
+            Ok(snapshot) => {
+                let cycles = snapshot.cycles;
+                if let Some(history) = self.history {
+                    history.insert(canister_id, snapshot);
+                }
+                cycles
+            }
+            Err(err) => {
+                self.update(canister_id, |canister| canister.last_checked_at = Some(now));
+                self.log(
+                    canister_id,
+                    CyclesMonitorAction::StatusFailed { error: err.clone() },
+                );
+                return Err(err);
+            }
+        };
+
+        let canister = self
+            .update(canister_id, |canister| {
+                // a balance increase (e.g. a top-up) keeps the previous estimate
+                if let (Some(last_cycles), Some(last_checked_at)) =
+                    (canister.last_cycles, canister.last_checked_at)
+                {
+                    let elapsed = u128::from(now.saturating_sub(last_checked_at));
+                    if last_cycles >= cycles && elapsed > 0 {
+                        canister.burn_rate_cycles_per_day =
+                            Some((last_cycles - cycles).saturating_mul(NANOS_PER_DAY) / elapsed);
+                    }
+                }
+                canister.last_cycles = Some(cycles);
+                canister.last_checked_at = Some(now);
+            })
+            .ok_or_else(|| Self::not_found(canister_id, "check"))?;
+
+        self.log(
+            canister_id,
+            CyclesMonitorAction::StatusFetched {
+                cycles,
+                burn_rate_cycles_per_day: canister.burn_rate_cycles_per_day,
+            },
+        );
+
+        if canister.pending_top_up_block.is_some()
+            || (cycles < canister.threshold_cycles
+                && !canister.is_cooling_down(self.interval_seconds, now))
+        {
+            self.top_up_on(network, canister_id).await?;
+            return self.get(canister_id);
+        }
+
+        Ok(canister)
+    }
+
+    /// Top up a registered canister with its configured method, regardless of its balance.
+    ///
+    /// When an earlier ICP transfer to the CMC was not notified yet, only the notify is retried.
+    /// # Returns
+    /// * `Result<Nat, ApiError>` - The cycles minted or deposited, or a `Conflict` error
+    ///   when the canister is already being topped up
+    pub async fn top_up_on(
+        &self,
+        network: &NetworkConfig,
+        canister_id: Principal,
+    ) -> CanisterResult<Nat> {
+        let _lock = TopUpLock::acquire(canister_id).ok_or_else(|| {
+            ApiError::conflict("Canister top-up already in progress")
+                .add_method_name("top_up")
+                .add_info(canister_id)
+                .add_source("toolkit_utils")
+        })?;
+        let canister = self.get(canister_id)?;
+
+        let result = match (canister.pending_top_up_block, canister.top_up.clone()) {
+            (Some(block_index), _) => self.notify_top_up(network, canister_id, block_index).await,
+            (None, TopUpMethod::Icp { amount_e8s }) => {
+                self.top_up_with_icp(network, canister_id, amount_e8s).await
+            }
+            (None, TopUpMethod::DepositCycles { cycles }) => {
+                deposit_cycles(&DepositCyclesArgs { canister_id }, cycles)
+                    .await
+                    .map(|_| Nat::from(cycles))
+                    .map_err(Box::<ApiError>::from)
+            }
+        }
+        .map_err(|err| {
+            err.add_method_name("top_up")
+                .add_info(canister_id)
+                .add_source("toolkit_utils")
+        });
+
+        match &result {
+            Ok(cycles) => {
+                self.update(canister_id, |canister| {
+                    canister.last_topped_up_at = Some(time())
+                });
+                self.log(
+                    canister_id,
+                    CyclesMonitorAction::ToppedUp {
+                        method: canister.top_up,
+                        cycles: cycles.clone(),
+                    },
+                );
+            }
+            Err(err) => self.log(
+                canister_id,
+                CyclesMonitorAction::TopUpFailed {
+                    method: canister.top_up,
+                    error: err.clone(),
+                },
+            ),
+        }
+
+        result
+    }
+
+    async fn fetch_cycles(&self, canister_id: Principal) -> CanisterResult<CyclesSnapshot> {
+        let status = canister_status(&CanisterStatusArgs { canister_id })
+            .await
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name("canister_status")
+                    .add_info(canister_id)
+                    .add_source("toolkit_utils")
+            })?;
+
+        CyclesSnapshot::from_status(&status)
+    }
+
+    // the block of the transfer is stored before the notify, so a failed notify does not send ICP again
+    async fn top_up_with_icp(
+        &self,
+        network: &NetworkConfig,
+        canister_id: Principal,
+        amount_e8s: u64,
+    ) -> CanisterResult<Nat> {
+        let block_index = top_up_cycles_on(network, amount_e8s, canister_id).await?;
+        self.update(canister_id, |canister| {
+            canister.pending_top_up_block = Some(block_index)
+        });
+
+        self.notify_top_up(network, canister_id, block_index).await
+    }
+
+    // the pending block is cleared once the CMC minted the cycles or the notify can't succeed anymore
+    async fn notify_top_up(
+        &self,
+        network: &NetworkConfig,
+        canister_id: Principal,
+        block_index: u64,
+    ) -> CanisterResult<Nat> {
+        let result =
+            notify_top_up_cycles_external_canister_on(network, block_index, canister_id).await;
+
+        let settled = match &result {
+            Ok(_) => true,
+            Err(err) => matches!(
+                err.error_type(),
+                ApiErrorType::NotifyError(
+                    NotifyError::Refunded { .. }
+                        | NotifyError::InvalidTransaction(_)
+                        | NotifyError::TransactionTooOld(_)
+                )
+            ),
+        };
+        if settled {
+            self.update(canister_id, |canister| canister.pending_top_up_block = None);
+        }
+
+        result
+    }
+
+    // applies `f` to the current record, a canister that was unregistered in the meantime stays removed
+    fn update(
+        &self,
+        canister_id: Principal,
+        f: impl FnOnce(&mut MonitoredCanister),
+    ) -> Option<MonitoredCanister> {
+        self.canisters.with(|data| {
+            let mut canister = data.borrow().get(&canister_id)?;
+            f(&mut canister);
+            data.borrow_mut().insert(canister_id, canister.clone());
+            Some(canister)
+        })
+    }
+
+    fn log(&self, canister_id: Principal, action: CyclesMonitorAction) {
+        self.events.with(|data| {
+            let id = data
+                .borrow()
+                .last_key_value()
+                .map(|(k, _)| k + 1)
+                .unwrap_or(1);
+            data.borrow_mut()
+                .insert(id, CyclesMonitorEvent::new(canister_id, action));
+        });
+    }
+
+    fn not_found(canister_id: Principal, method: &str) -> Box<ApiError> {
+        ApiError::not_found("Canister is not monitored")
+            .add_method_name(method)
+            .add_info(canister_id)
+            .add_source("toolkit_utils")
+    }
+}
@@ -0,0 +1,323 @@
+use std::{cell::Cell, ops::Bound};
+
+use candid::Principal;
+use ic_ledger_types::{account_balance, AccountBalanceArgs, AccountIdentifier, Subaccount};
+
+use crate::{
+    api_error::ApiError,
+    misc::{
+        reentrancy::ReentrancyGuard,
+        runtime::{canister_self, time},
+    },
+    network_config::NetworkConfig,
+    result::CanisterResult,
+    sweep_account::SweepAccount,
+    sweep_record::SweepRecord,
+    transfer_journal_entry::{TransferJournalEntry, TransferStatus},
+    StaticStorageRef,
+};
+
+use super::{
+    book_ledger::BookLedger, network::network_config, transactions::icp_transfer_fee_on,
+    transfer_journal::TransferJournal,
+};
+
+pub static DEFAULT_SWEEP_THRESHOLD_E8S: u64 = 1_000_000; // 0.01 ICP
+pub static DEFAULT_SWEEP_CHUNK_SIZE: usize = 20;
+
+thread_local! {
+    static SWEEP_CURSOR: Cell<Option<Principal>> = const { Cell::new(None) };
+    static SWEEPING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Consolidates ICP deposited in per-user subaccounts (`Subaccount::from(principal)`) into a treasury account
+/// and credits the swept amount to the user in the `BookLedger`.
+///
+/// The transfers go through the `TransferJournal`, so a retry is deduplicated by the ledger and stops
+/// after the max attempts of the journal. Call `sweep_next_chunk` from a timer, it continues where the
+/// previous chunk stopped.
+#[derive(Clone, Copy)]
+pub struct DepositSweeper {
+    accounts: StaticStorageRef<Principal, SweepAccount>,
+    sweeps: StaticStorageRef<u64, SweepRecord>,
+    journal: TransferJournal,
+    book: BookLedger,
+    treasury: AccountIdentifier,
+    threshold_e8s: u64,
+    chunk_size: usize,
+}
+
+impl DepositSweeper {
+    pub fn new(
+        accounts: StaticStorageRef<Principal, SweepAccount>,
+        sweeps: StaticStorageRef<u64, SweepRecord>,
+        journal: TransferJournal,
+        book: BookLedger,
+        treasury: AccountIdentifier,
+    ) -> Self {
+        Self {
+            accounts,
+            sweeps,
+            journal,
+            book,
+            treasury,
+            threshold_e8s: DEFAULT_SWEEP_THRESHOLD_E8S,
+            chunk_size: DEFAULT_SWEEP_CHUNK_SIZE,
+        }
+    }
+
+    /// Subaccounts with a balance below the threshold are skipped
+    pub fn with_threshold(mut self, threshold_e8s: u64) -> Self {
+        self.threshold_e8s = threshold_e8s;
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn register(&self, principal: Principal) -> SweepAccount {
+        self.accounts.with(|data| {
+            let account = data.borrow().get(&principal).unwrap_or_default();
+            data.borrow_mut().insert(principal, account.clone());
+            account
+        })
+    }
+
+    pub fn unregister(&self, principal: Principal) -> CanisterResult<SweepAccount> {
+        let account = self.get_account(principal)?;
+        if self.book.get_user_balance(principal) > 0 {
+            return Err(ApiError::conflict("Account still has a credited balance")
+                .add_method_name("unregister")
+                .add_info(principal)
+                .add_source("toolkit_utils"));
+        }
+
+        self.accounts
+            .with(|data| data.borrow_mut().remove(&principal));
+        Ok(account)
+    }
+
+    pub fn get_account(&self, principal: Principal) -> CanisterResult<SweepAccount> {
+        self.accounts
+            .with(|data| data.borrow().get(&principal))
+            .ok_or_else(|| {
+                ApiError::not_found("Deposit account not registered")
+                    .add_method_name("get_account")
+                    .add_info(principal)
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    /// The deposit address of a principal
+    pub fn deposit_account_identifier(&self, principal: Principal) -> AccountIdentifier {
+        AccountIdentifier::new(&canister_self(), &Subaccount::from(principal))
+    }
+
+    pub fn get_sweeps(&self, principal: Principal) -> Vec<(u64, SweepRecord)> {
+        self.sweeps.with(|data| {
+            data.borrow()
+                .iter()
+                .filter(|(_, sweep)| sweep.principal == principal)
+                .collect()
+        })
+    }
+
+    /// Retry the pending sweeps and sweep the next `chunk_size` registered subaccounts
+    /// # Returns
+    /// * `Vec<(Principal, Result<u64, ApiError>)>` - The credited amount per swept principal
+    pub async fn sweep_next_chunk(&self) -> Vec<(Principal, CanisterResult<u64>)> {
+        self.sweep_next_chunk_on(&network_config()).await
+    }
+
+    pub async fn sweep_next_chunk_on(
+        &self,
+        network: &NetworkConfig,
+    ) -> Vec<(Principal, CanisterResult<u64>)> {
+        let Some(_guard) = ReentrancyGuard::acquire(&SWEEPING) else {
+            return vec![];
+        };
+
+        let mut results = vec![];
+
+        for (id, sweep) in self.get_retryable_sweeps() {
+            results.push((sweep.principal, self.execute(id).await));
+        }
+
+        for principal in self.next_principals() {
+            results.push((principal, self.sweep_on(network, principal).await));
+        }
+
+        results
+    }
+
+    /// Sweep a single deposit subaccount when its balance reaches the threshold
+    /// # Returns
+    /// * `Result<u64, ApiError>` - The amount credited to the principal, `0` when below the threshold
+    pub async fn sweep_on(
+        &self,
+        network: &NetworkConfig,
+        principal: Principal,
+    ) -> CanisterResult<u64> {
+        self.get_account(principal)?;
+
+        // the balance still includes the amount of an uncredited sweep
+        if self
+            .get_sweeps(principal)
+            .iter()
+            .any(|(_, sweep)| !sweep.credited && self.is_pending(sweep))
+        {
+            return Ok(0);
+        }
+
+        let balance = account_balance(
+            network.ledger_canister_id,
+            &AccountBalanceArgs {
+                account: self.deposit_account_identifier(principal),
+            },
+        )
+        .await
+        .map_err(|err| {
+            ApiError::from(err)
+                .add_method_name("sweep")
+                .add_info(principal)
+                .add_source("toolkit_utils")
+        })?
+        .e8s();
+
+        let fee_e8s = icp_transfer_fee_on(network).await?;
+        if balance < self.threshold_e8s || balance <= fee_e8s {
+            return Ok(0);
+        }
+
+        let (journal_id, entry) = self.journal.record(TransferJournalEntry::new(
+            network.ledger_canister_id,
+            Some(Subaccount::from(principal)),
+            self.treasury,
+            balance - fee_e8s,
+            fee_e8s,
+            0,
+        ))?;
+
+        let id = self.sweeps.with(|data| {
+            let id = data
+                .borrow()
+                .last_key_value()
+                .map(|(k, _)| k + 1)
+                .unwrap_or(1);
+            data.borrow_mut().insert(
+                id,
+                SweepRecord {
+                    principal,
+                    journal_id,
+                    amount_e8s: entry.amount_e8s,
+                    credited: false,
+                },
+            );
+            id
+        });
+
+        self.execute(id).await
+    }
+
+    // uncredited sweeps whose transfer completed or can still be attempted
+    fn get_retryable_sweeps(&self) -> Vec<(u64, SweepRecord)> {
+        self.sweeps
+            .with(|data| {
+                data.borrow()
+                    .iter()
+                    .filter(|(_, sweep)| !sweep.credited)
+                    .collect::<Vec<_>>()
+            })
+            .into_iter()
+            .filter(|(_, sweep)| match self.journal.get(sweep.journal_id) {
+                Ok(entry) => match entry.status {
+                    TransferStatus::Completed { .. } => true,
+                    TransferStatus::Pending => entry.attempts < self.journal.max_attempts(),
+                    TransferStatus::Failed { .. } => false,
+                },
+                Err(_) => false,
+            })
+            .collect()
+    }
+
+    fn is_pending(&self, sweep: &SweepRecord) -> bool {
+        self.journal
+            .get(sweep.journal_id)
+            .map(|entry| !matches!(entry.status, TransferStatus::Failed { .. }))
+            .unwrap_or(false)
+    }
+
+    // executes the journaled transfer of a sweep and credits it once completed
+    async fn execute(&self, id: u64) -> CanisterResult<u64> {
+        let Some(sweep) = self.sweeps.with(|data| data.borrow().get(&id)) else {
+            return Ok(0);
+        };
+
+        let block_index = self
+            .journal
+            .execute(sweep.journal_id)
+            .await
+            .map_err(|err| err.add_method_name("sweep").add_info(sweep.principal))?;
+
+        self.credit(id, block_index)
+    }
+
+    fn credit(&self, id: u64, block_index: u64) -> CanisterResult<u64> {
+        let Some(mut sweep) = self.sweeps.with(|data| data.borrow().get(&id)) else {
+            return Ok(0);
+        };
+        if sweep.credited {
+            return Ok(sweep.amount_e8s);
+        }
+
+        self.book
+            .record_deposit(sweep.principal, sweep.amount_e8s, Some(block_index))
+            .map_err(|err| err.add_method_name("sweep").add_info(sweep.principal))?;
+
+        sweep.credited = true;
+        self.sweeps
+            .with(|data| data.borrow_mut().insert(id, sweep.clone()));
+
+        self.accounts.with(|data| {
+            let mut account = data.borrow().get(&sweep.principal).unwrap_or_default();
+            account.total_swept_e8s = account.total_swept_e8s.saturating_add(sweep.amount_e8s);
+            account.last_swept_at = Some(time());
+            data.borrow_mut().insert(sweep.principal, account);
+        });
+
+        Ok(sweep.amount_e8s)
+    }
+
+    // the next registered principals after the cursor, wrapping around at the end
+    fn next_principals(&self) -> Vec<Principal> {
+        let cursor = SWEEP_CURSOR.with(|cursor| cursor.get());
+
+        let principals: Vec<Principal> = self.accounts.with(|data| {
+            let data = data.borrow();
+            let after = match cursor {
+                Some(cursor) => data
+                    .range((Bound::Excluded(cursor), Bound::Unbounded))
+                    .map(|(principal, _)| principal)
+                    .take(self.chunk_size)
+                    .collect::<Vec<_>>(),
+                None => vec![],
+            };
+
+            let remaining = self.chunk_size.saturating_sub(after.len());
+            after
+                .into_iter()
+                .chain(
+                    data.iter()
+                        .map(|(principal, _)| principal)
+                        .take_while(|principal| Some(*principal) <= cursor || cursor.is_none())
+                        .take(remaining),
+                )
+                .collect()
+        });
+
+        SWEEP_CURSOR.with(|cursor| cursor.set(principals.last().copied()));
+        principals
+    }
+}
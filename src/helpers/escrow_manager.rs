@@ -0,0 +1,398 @@
+use std::{cell::RefCell, collections::BTreeSet};
+
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc1::{
+    account::Account,
+    transfer::{BlockIndex, TransferError},
+};
+
+use crate::{
+    api_error::{ApiError, ApiErrorType},
+    escrow::{Escrow, EscrowPayout, EscrowStatus},
+    misc::runtime::{canister_self, msg_caller, time},
+    result::CanisterResult,
+    token_ledger::TokenLedger,
+    StaticStorageRef,
+};
+
+use super::subaccounts::{derive_subaccount, SUBACCOUNT_DOMAIN_ESCROW};
+
+thread_local! {
+    // escrows with a release or refund in flight, guards against a second payout during the await
+    static ESCROW_LOCKS: RefCell<BTreeSet<u64>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+enum PayoutKind {
+    Release,
+    Refund,
+}
+
+impl PayoutKind {
+    fn method_name(&self) -> &'static str {
+        match self {
+            PayoutKind::Release => "release",
+            PayoutKind::Refund => "refund",
+        }
+    }
+}
+
+// released on drop, so a trap in a callback does not keep the escrow locked
+struct EscrowLock(u64);
+
+impl EscrowLock {
+    fn acquire(id: u64) -> Option<Self> {
+        ESCROW_LOCKS
+            .with(|locks| locks.borrow_mut().insert(id))
+            .then_some(Self(id))
+    }
+}
+
+impl Drop for EscrowLock {
+    fn drop(&mut self) {
+        ESCROW_LOCKS.with(|locks| locks.borrow_mut().remove(&self.0));
+    }
+}
+
+/// Escrows stored in stable memory, each holding its funds in its own subaccount of this canister.
+///
+/// Call `process_expired` from a timer to refund or expire escrows that passed their deadline.
+#[derive(Clone, Copy)]
+pub struct EscrowManager {
+    storage: StaticStorageRef<u64, Escrow>,
+}
+
+impl EscrowManager {
+    pub fn new(storage: StaticStorageRef<u64, Escrow>) -> Self {
+        Self { storage }
+    }
+
+    /// Open a new escrow
+    /// # Arguments
+    /// * `ledger_canister_id` - The ICRC-1 ledger of the escrowed token
+    /// * `depositor` - The account that funds the escrow and receives refunds
+    /// * `beneficiary` - The account that receives the funds on release
+    /// * `amount` - The amount that has to be deposited to fund the escrow
+    /// * `deadline` - The time (nanoseconds) after which the escrow is refunded
+    /// # Returns
+    /// * `Result<(u64, Escrow), ApiError>` - The escrow id and the escrow
+    pub fn create(
+        &self,
+        ledger_canister_id: Principal,
+        depositor: Account,
+        beneficiary: Account,
+        amount: Nat,
+        deadline: u64,
+    ) -> CanisterResult<(u64, Escrow)> {
+        if amount == 0u64 {
+            return Err(
+                ApiError::bad_request("Escrow amount must be greater than zero")
+                    .add_method_name("create")
+                    .add_info("escrow")
+                    .add_source("toolkit_utils"),
+            );
+        }
+
+        self.storage.with(|data| {
+            let id = data
+                .borrow()
+                .last_key_value()
+                .map(|(k, _)| k + 1)
+                .unwrap_or(1);
+
+            let escrow = Escrow::new(
+                ledger_canister_id,
+                derive_subaccount(SUBACCOUNT_DOMAIN_ESCROW, depositor.owner, id).0,
+                depositor,
+                beneficiary,
+                amount,
+                deadline,
+                msg_caller(),
+            );
+
+            data.borrow_mut().insert(id, escrow.clone());
+            Ok((id, escrow))
+        })
+    }
+
+    pub fn get(&self, id: u64) -> CanisterResult<Escrow> {
+        self.storage
+            .with(|data| data.borrow().get(&id))
+            .ok_or_else(|| {
+                ApiError::not_found("Escrow not found")
+                    .add_method_name("get")
+                    .add_info(id)
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    pub fn get_by_status(&self, status: EscrowStatus) -> Vec<(u64, Escrow)> {
+        self.storage.with(|data| {
+            data.borrow()
+                .iter()
+                .filter(|(_, escrow)| escrow.status == status)
+                .collect()
+        })
+    }
+
+    /// The account the depositor has to send the funds to
+    pub fn deposit_account(&self, id: u64) -> CanisterResult<Account> {
+        let escrow = self.get(id)?;
+        Ok(Account {
+            owner: canister_self(),
+            subaccount: Some(escrow.subaccount),
+        })
+    }
+
+    /// Check the balance of the escrow subaccount and mark the escrow as funded when it covers the amount
+    /// # Returns
+    /// * `Result<Escrow, ApiError>` - The (updated) escrow
+    pub async fn check_deposit(&self, id: u64) -> CanisterResult<Escrow> {
+        let mut escrow = self.get(id)?;
+        if escrow.status != EscrowStatus::Open {
+            return Ok(escrow);
+        }
+
+        let balance = self.balance(&escrow).await?;
+        if balance >= escrow.amount {
+            escrow = self.get(id)?;
+            if escrow.status == EscrowStatus::Open {
+                escrow.set_status(EscrowStatus::Funded);
+                self.save(id, &escrow);
+            }
+        }
+
+        Ok(escrow)
+    }
+
+    /// Release the escrowed amount, minus the ledger fee, to the beneficiary
+    pub async fn release(&self, id: u64) -> CanisterResult<BlockIndex> {
+        self.check_deposit(id).await?;
+        self.pay_out(id, PayoutKind::Release).await
+    }
+
+    /// Refund the deposit, up to the escrowed amount and minus the ledger fee, to the depositor
+    pub async fn refund(&self, id: u64) -> CanisterResult<BlockIndex> {
+        self.check_deposit(id).await?;
+        self.pay_out(id, PayoutKind::Refund).await
+    }
+
+    /// Refund every unsettled escrow past its deadline, escrows without a refundable deposit expire
+    /// # Returns
+    /// * `Vec<(u64, Result<EscrowStatus, ApiError>)>` - The new status per processed escrow
+    pub async fn process_expired(&self) -> Vec<(u64, CanisterResult<EscrowStatus>)> {
+        let expired: Vec<(u64, Escrow)> = self.storage.with(|data| {
+            data.borrow()
+                .iter()
+                .filter(|(_, escrow)| !escrow.is_settled() && escrow.is_expired())
+                .collect()
+        });
+
+        let mut results = vec![];
+        for (id, escrow) in expired {
+            let result = match self.refundable_amount(&escrow).await {
+                Ok(Some(_)) => self
+                    .refund(id)
+                    .await
+                    .map(|block_index| EscrowStatus::Refunded { block_index }),
+                Ok(None) => self.expire(id),
+                Err(err) => Err(err),
+            };
+            results.push((id, result));
+        }
+        results
+    }
+
+    // pays out under the escrow lock, the status is checked on the stored escrow after taking the lock
+    async fn pay_out(&self, id: u64, kind: PayoutKind) -> CanisterResult<BlockIndex> {
+        let method_name = kind.method_name();
+        let _lock = EscrowLock::acquire(id).ok_or_else(|| {
+            ApiError::conflict("Escrow payout already in progress")
+                .add_method_name(method_name)
+                .add_info(id)
+                .add_source("toolkit_utils")
+        })?;
+
+        let mut escrow = self.get(id)?;
+        let rejection = match kind {
+            PayoutKind::Release if escrow.status != EscrowStatus::Funded => {
+                Some("Escrow is not funded")
+            }
+            // a pending release is still retried after the deadline, it may already be on the ledger
+            PayoutKind::Release if escrow.is_expired() && escrow.payout.is_none() => {
+                Some("Escrow is expired")
+            }
+            PayoutKind::Refund if escrow.is_settled() => Some("Escrow is already settled"),
+            _ => None,
+        };
+        if let Some(message) = rejection {
+            return Err(ApiError::conflict(message)
+                .add_method_name(method_name)
+                .add_info(id)
+                .add_source("toolkit_utils"));
+        }
+
+        let to = match kind {
+            PayoutKind::Release => escrow.beneficiary,
+            PayoutKind::Refund => escrow.depositor,
+        };
+
+        let payout = match escrow.payout.clone() {
+            Some(payout) if payout.to != to => {
+                return Err(
+                    ApiError::conflict("Escrow has a pending payout to another account")
+                        .add_method_name(method_name)
+                        .add_info(id)
+                        .add_source("toolkit_utils"),
+                );
+            }
+            Some(payout) => payout,
+            None => {
+                let payout = EscrowPayout {
+                    to,
+                    amount: self.payout_amount(id, &escrow, &kind).await?,
+                    created_at_time: time(),
+                };
+                escrow = self.get(id)?;
+                escrow.set_payout(Some(payout.clone()));
+                self.save(id, &escrow);
+                payout
+            }
+        };
+
+        let result = TokenLedger::new(escrow.ledger_canister_id)
+            .transfer(
+                Some(escrow.subaccount),
+                payout.to,
+                payout.amount,
+                None,
+                Some(payout.created_at_time),
+            )
+            .await;
+
+        let block_index = match result {
+            Ok(block_index) => block_index,
+            Err(err) => match duplicate_of(&err) {
+                Some(block_index) => block_index,
+                None => {
+                    // the ledger rejected the transfer, a next attempt computes a new payout
+                    if is_rejected_by_ledger(&err) {
+                        if let Ok(mut escrow) = self.get(id) {
+                            escrow.set_payout(None);
+                            self.save(id, &escrow);
+                        }
+                    }
+                    return Err(err.add_method_name(method_name));
+                }
+            },
+        };
+
+        let status = match kind {
+            PayoutKind::Release => EscrowStatus::Released {
+                block_index: block_index.clone(),
+            },
+            PayoutKind::Refund => EscrowStatus::Refunded {
+                block_index: block_index.clone(),
+            },
+        };
+        self.settle(id, status);
+        Ok(block_index)
+    }
+
+    // the amount the beneficiary receives is the escrowed amount, a refund is capped by it
+    async fn payout_amount(
+        &self,
+        id: u64,
+        escrow: &Escrow,
+        kind: &PayoutKind,
+    ) -> CanisterResult<Nat> {
+        let gross = match kind {
+            PayoutKind::Release => escrow.amount.clone(),
+            PayoutKind::Refund => self.balance(escrow).await?.min(escrow.amount.clone()),
+        };
+        let fee = TokenLedger::new(escrow.ledger_canister_id)
+            .icrc1_fee()
+            .await?;
+
+        if gross > fee {
+            Ok(gross - fee)
+        } else {
+            Err(
+                ApiError::bad_request("Escrow balance does not cover the ledger fee")
+                    .add_method_name(kind.method_name())
+                    .add_info(id)
+                    .add_source("toolkit_utils"),
+            )
+        }
+    }
+
+    fn expire(&self, id: u64) -> CanisterResult<EscrowStatus> {
+        let _lock = EscrowLock::acquire(id).ok_or_else(|| {
+            ApiError::conflict("Escrow payout already in progress")
+                .add_method_name("process_expired")
+                .add_info(id)
+                .add_source("toolkit_utils")
+        })?;
+
+        let escrow = self.get(id)?;
+        if escrow.is_settled() || escrow.payout.is_some() {
+            return Ok(escrow.status);
+        }
+
+        self.settle(id, EscrowStatus::Expired);
+        Ok(EscrowStatus::Expired)
+    }
+
+    // the balance of the escrow subaccount minus the ledger fee, `None` when it can't cover the fee
+    async fn refundable_amount(&self, escrow: &Escrow) -> CanisterResult<Option<Nat>> {
+        let ledger = TokenLedger::new(escrow.ledger_canister_id);
+        let balance = self.balance(escrow).await?;
+        let fee = ledger.icrc1_fee().await?;
+
+        if balance > fee {
+            Ok(Some(balance - fee))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn balance(&self, escrow: &Escrow) -> CanisterResult<Nat> {
+        TokenLedger::new(escrow.ledger_canister_id)
+            .icrc1_balance_of(Account {
+                owner: canister_self(),
+                subaccount: Some(escrow.subaccount),
+            })
+            .await
+    }
+
+    fn settle(&self, id: u64, status: EscrowStatus) {
+        if let Ok(mut escrow) = self.get(id) {
+            escrow.set_status(status);
+            escrow.set_payout(None);
+            self.save(id, &escrow);
+        }
+    }
+
+    fn save(&self, id: u64, escrow: &Escrow) {
+        self.storage
+            .with(|data| data.borrow_mut().insert(id, escrow.clone()));
+    }
+}
+
+fn duplicate_of(err: &ApiError) -> Option<BlockIndex> {
+    match err.error_type() {
+        ApiErrorType::Icrc1TransferError(TransferError::Duplicate { duplicate_of }) => {
+            Some(duplicate_of.clone())
+        }
+        _ => None,
+    }
+}
+
+fn is_rejected_by_ledger(err: &ApiError) -> bool {
+    matches!(
+        err.error_type(),
+        ApiErrorType::Icrc1TransferError(err) if !matches!(
+            err,
+            TransferError::TemporarilyUnavailable | TransferError::CreatedInFuture { .. }
+        )
+    )
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use crate::{
+    api_error::{ApiError, ApiErrorType},
+    error_code::{ErrorParam, ValidationCode},
+    validation::ValidationResponse,
+};
+
+pub static DEFAULT_LOCALE: &str = "en";
+
+/// Turns error codes and their parameters into text for a requested locale.
+///
+/// Templates use `{key}` placeholders that are replaced by the matching `ErrorParam`.
+/// Lookups fall back from `nl-BE` to `nl` and finally to the default locale (English).
+#[derive(Clone, Debug)]
+pub struct MessageCatalog {
+    default_locale: String,
+    templates: HashMap<String, HashMap<u32, String>>,
+}
+
+impl Default for MessageCatalog {
+    fn default() -> Self {
+        use ValidationCode::*;
+        let mut catalog = Self {
+            default_locale: DEFAULT_LOCALE.to_string(),
+            templates: HashMap::new(),
+        };
+
+        let error_templates = [
+            (
+                ApiErrorType::NOT_IMPLEMENTED,
+                "This functionality is not implemented",
+            ),
+            (ApiErrorType::UNEXPECTED, "An unexpected error occurred"),
+            (ApiErrorType::UNAUTHORIZED, "You are not authorized"),
+            (ApiErrorType::NOT_FOUND, "The requested item was not found"),
+            (ApiErrorType::BAD_REQUEST, "The request is invalid"),
+            (ApiErrorType::UNSUPPORTED, "This request is not supported"),
+            (ApiErrorType::DUPLICATE, "The item already exists"),
+            (
+                ApiErrorType::VALIDATION_ERROR,
+                "One or more fields are invalid",
+            ),
+            (
+                ApiErrorType::SERIALIZE_ERROR,
+                "The data could not be serialized",
+            ),
+            (
+                ApiErrorType::DESERIALIZE_ERROR,
+                "The data could not be deserialized",
+            ),
+            (ApiErrorType::PAYLOAD_TOO_LARGE, "The request is too large"),
+            (
+                ApiErrorType::SERVICE_UNAVAILABLE,
+                "The service is unavailable",
+            ),
+            (
+                ApiErrorType::CONFLICT,
+                "The request conflicts with the current state",
+            ),
+            (ApiErrorType::FORBIDDEN, "You are not allowed to do this"),
+            (
+                ApiErrorType::EXTERNAL_SERVICE_ERROR,
+                "An external service returned an error",
+            ),
+            (ApiErrorType::DEPRECATED, "This functionality is deprecated"),
+            (ApiErrorType::ICP_TRANSFER_ERROR, "The ICP transfer failed"),
+            (
+                ApiErrorType::ICRC1_TRANSFER_ERROR,
+                "The token transfer failed",
+            ),
+            (
+                ApiErrorType::ICRC2_TRANSFER_FROM_ERROR,
+                "The approved token transfer failed",
+            ),
+            (
+                ApiErrorType::ICRC2_APPROVE_ERROR,
+                "The token approval failed",
+            ),
+            (
+                ApiErrorType::NOTIFY_ERROR,
+                "The cycles minting canister returned an error",
+            ),
+            (
+                ApiErrorType::CANISTER_CALL_ERROR,
+                "A call to another canister failed",
+            ),
+            (
+                ApiErrorType::CYCLES_LEDGER_WITHDRAW_ERROR,
+                "Withdrawing cycles from the cycles ledger failed",
+            ),
+            (
+                ApiErrorType::CYCLES_LEDGER_CREATE_CANISTER_ERROR,
+                "Creating a canister from the cycles ledger failed",
+            ),
+        ];
+
+        for (code, template) in error_templates {
+            catalog = catalog.add_template(DEFAULT_LOCALE, code, template);
+        }
+
+        for (code, template) in [
+            (MinLength.code(), "Minimum required length is {min}"),
+            (MaxLength.code(), "Maximum length is {max}"),
+            (MinCount.code(), "Minimum size length is {min}"),
+            (MaxCount.code(), "Maximum size is {max}"),
+            (InvalidEmail.code(), "Invalid email address: {reason}"),
+            (
+                StartDateAfterEndDate.code(),
+                "The start date is after the end date",
+            ),
+            (
+                StartDateInPast.code(),
+                "The start date can't be in the past",
+            ),
+        ] {
+            catalog = catalog.add_template(DEFAULT_LOCALE, code, template);
+        }
+
+        catalog
+    }
+}
+
+impl MessageCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_template<L: ToString, T: ToString>(
+        mut self,
+        locale: L,
+        code: u32,
+        template: T,
+    ) -> Self {
+        self.templates
+            .entry(locale.to_string().to_lowercase())
+            .or_default()
+            .insert(code, template.to_string());
+        self
+    }
+
+    /// Render the template of a code for the locale
+    /// # Arguments
+    /// * `code` - The error code
+    /// * `params` - The parameters to fill the placeholders with
+    /// * `locale` - The requested locale, e.g. `nl` or `nl-BE`
+    /// # Returns
+    /// * `Option<String>` - The rendered message, `None` if no template exists in any fallback locale
+    pub fn translate(&self, code: u32, params: &[ErrorParam], locale: &str) -> Option<String> {
+        self.template(code, locale)
+            .or_else(|| self.template(code, &self.default_locale))
+            .map(|template| render(template, params))
+    }
+
+    /// Localize an `ApiError`, for the default locale the specific message of the error is kept.
+    pub fn localize_error(&self, error: &ApiError, locale: &str) -> String {
+        if self.base_locale(locale) != self.default_locale {
+            if let Some(template) = self.template(error.code(), locale) {
+                return render(template, &error.params());
+            }
+        }
+
+        if !error.message().is_empty() {
+            return error.message().to_string();
+        }
+
+        self.translate(error.code(), &error.params(), &self.default_locale)
+            .unwrap_or_default()
+    }
+
+    /// Localize a `ValidationResponse`, falls back to its message when it has no code.
+    pub fn localize_validation(&self, response: &ValidationResponse, locale: &str) -> String {
+        response
+            .code
+            .and_then(|code| {
+                self.translate(code, &response.params.clone().unwrap_or_default(), locale)
+            })
+            .unwrap_or_else(|| response.message.clone())
+    }
+
+    fn template(&self, code: u32, locale: &str) -> Option<&String> {
+        self.template_locale(code, locale)
+            .and_then(|locale| self.templates.get(&locale))
+            .and_then(|templates| templates.get(&code))
+    }
+
+    fn template_locale(&self, code: u32, locale: &str) -> Option<String> {
+        let locale = locale.to_lowercase();
+        let base_locale = self.base_locale(&locale);
+        [locale, base_locale].into_iter().find(|locale| {
+            self.templates
+                .get(locale)
+                .is_some_and(|templates| templates.contains_key(&code))
+        })
+    }
+
+    fn base_locale(&self, locale: &str) -> String {
+        locale
+            .to_lowercase()
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+fn render(template: &str, params: &[ErrorParam]) -> String {
+    params.iter().fold(template.to_string(), |message, param| {
+        message.replace(&format!("{{{}}}", param.key), &param.value)
+    })
+}
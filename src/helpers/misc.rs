@@ -5,6 +5,7 @@ pub fn principal_to_account_identifier(principal: Principal) -> AccountIdentifie
     AccountIdentifier::new(&principal, &DEFAULT_SUBACCOUNT)
 }
 
+#[deprecated(note = "loses precision, use `TokenAmount` instead")]
 pub fn nat_to_f64(n: &Nat) -> f64 {
     let n_str = n.0.to_string();
     n_str.parse::<f64>().unwrap()
@@ -14,18 +15,25 @@ pub fn f64_to_u64(f: f64) -> u64 {
     f.round() as u64
 }
 
+#[deprecated(note = "rounds through f64, use `conversions::try_nat_to_u64` instead")]
+#[allow(deprecated)]
 pub fn nat_to_u64(n: &Nat) -> u64 {
     f64_to_u64(nat_to_f64(n))
 }
 
+#[deprecated(note = "loses precision, use `TokenAmount` instead")]
 pub fn f64_to_e8s(f: f64) -> Nat {
     Nat::from((f * 1e8) as u128)
 }
 
+#[deprecated(note = "loses precision, use `TokenAmount` instead")]
+#[allow(deprecated)]
 pub fn e8s_to_f64(n: &Nat) -> f64 {
     nat_to_f64(n) / 100000000.0
 }
 
+#[deprecated(note = "loses precision, use `TokenAmount` instead")]
+#[allow(deprecated)]
 pub fn e12s_to_f64(n: &Nat) -> f64 {
     nat_to_f64(n) / 1000000000000.0
 }
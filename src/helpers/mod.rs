@@ -1,8 +1,22 @@
+pub mod book_ledger;
 pub mod candid_save;
 pub mod canister;
+pub mod consumed_payments;
+pub mod conversions;
 pub mod cycles;
+pub mod cycles_history;
+pub mod cycles_monitor;
+pub mod deposit_sweeper;
+pub mod escrow_manager;
+pub mod message_catalog;
 pub mod misc;
+pub mod network;
+pub mod payout_batches;
+pub mod pricing;
+pub mod rate_provider;
 pub mod storage_init;
 pub mod str;
+pub mod subaccounts;
 pub mod transactions;
+pub mod transfer_journal;
 pub mod validator;
@@ -0,0 +1,58 @@
+use std::cell::RefCell;
+
+use candid::Principal;
+
+use crate::{
+    api_error::ApiError,
+    cell::{CellStorage, StaticCellStorageRef},
+    network_config::NetworkConfig,
+    result::CanisterResult,
+    GenericCellStorage,
+};
+
+thread_local! {
+    static NETWORK_CONFIG_STORAGE: RefCell<Option<StaticCellStorageRef<NetworkConfig>>> = const { RefCell::new(None) };
+}
+
+/// Register the cell that holds the network config, call this in `init` and `post_upgrade`.
+///
+/// Without a registered (and initialized) cell the helpers use `NetworkConfig::mainnet()`.
+pub fn register_network_config_storage(storage: StaticCellStorageRef<NetworkConfig>) {
+    NETWORK_CONFIG_STORAGE.with(|current| *current.borrow_mut() = Some(storage));
+}
+
+pub fn set_network_config(config: NetworkConfig) -> CanisterResult<NetworkConfig> {
+    let storage = NETWORK_CONFIG_STORAGE
+        .with(|current| *current.borrow())
+        .ok_or_else(|| {
+            ApiError::unexpected("Network config storage is not registered")
+                .add_method_name("set_network_config")
+                .add_source("toolkit_utils")
+        })?;
+
+    GenericCellStorage::new("network_config", storage).set(config)
+}
+
+/// The network config that the transaction and cycles helpers use.
+pub fn network_config() -> NetworkConfig {
+    NETWORK_CONFIG_STORAGE
+        .with(|current| *current.borrow())
+        .and_then(|storage| {
+            GenericCellStorage::new("network_config", storage)
+                .get()
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+pub fn ledger_canister_id() -> Principal {
+    network_config().ledger_canister_id
+}
+
+pub fn cycles_minting_canister_id() -> Principal {
+    network_config().cycles_minting_canister_id
+}
+
+pub fn cycles_ledger_canister_id() -> Principal {
+    network_config().cycles_ledger_canister_id
+}
@@ -0,0 +1,289 @@
+use std::cell::Cell;
+
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc1::{
+    account::{Account, Subaccount},
+    transfer::{Memo, TransferError},
+};
+
+use crate::{
+    api_error::{ApiError, ApiErrorType},
+    misc::{
+        reentrancy::ReentrancyGuard,
+        runtime::{msg_caller, time},
+    },
+    payout_batch::{PayoutBatch, PayoutBatchResponse, PayoutBatchStatus, PayoutChunkResult},
+    payout_item::{PayoutItem, PayoutStatus},
+    result::CanisterResult,
+    token_ledger::TokenLedger,
+    StaticStorageRef,
+};
+
+pub static DEFAULT_PAYOUT_CHUNK_SIZE: usize = 20;
+pub static DEFAULT_MAX_PAYOUT_ATTEMPTS: u32 = 5;
+
+thread_local! {
+    // a timer can fire again while the previous chunk is still awaiting the ledger
+    static PROCESSING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Persistent payout batches, executed in bounded chunks.
+///
+/// Batches and their items are stored separately, items by `(batch id, index)`.
+/// Call `process_next_chunk` from a timer until it returns `PayoutChunkResult::Done`.
+#[derive(Clone, Copy)]
+pub struct PayoutEngine {
+    batches: StaticStorageRef<u64, PayoutBatch>,
+    items: StaticStorageRef<(u64, u64), PayoutItem>,
+    chunk_size: usize,
+    max_attempts: u32,
+}
+
+impl PayoutEngine {
+    pub fn new(
+        batches: StaticStorageRef<u64, PayoutBatch>,
+        items: StaticStorageRef<(u64, u64), PayoutItem>,
+    ) -> Self {
+        Self {
+            batches,
+            items,
+            chunk_size: DEFAULT_PAYOUT_CHUNK_SIZE,
+            max_attempts: DEFAULT_MAX_PAYOUT_ATTEMPTS,
+        }
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Persist a new batch of payouts from a subaccount of this canister
+    /// # Returns
+    /// * `Result<(u64, PayoutBatchResponse), ApiError>` - The batch id and the batch
+    pub fn create_batch(
+        &self,
+        ledger_canister_id: Principal,
+        from_subaccount: Option<Subaccount>,
+        payouts: Vec<(Account, Nat, Option<Memo>)>,
+    ) -> CanisterResult<(u64, PayoutBatchResponse)> {
+        if payouts.is_empty() {
+            return Err(ApiError::bad_request("Payout batch is empty")
+                .add_method_name("create_batch")
+                .add_source("toolkit_utils"));
+        }
+
+        if payouts.iter().any(|(_, amount, _)| *amount == 0u64) {
+            return Err(
+                ApiError::bad_request("Payout amount must be greater than zero")
+                    .add_method_name("create_batch")
+                    .add_source("toolkit_utils"),
+            );
+        }
+
+        let batch = PayoutBatch::new(
+            ledger_canister_id,
+            from_subaccount,
+            payouts.len() as u64,
+            msg_caller(),
+        );
+        let items: Vec<PayoutItem> = payouts
+            .into_iter()
+            .map(|(to, amount, memo)| PayoutItem::new(to, amount, memo))
+            .collect();
+
+        let id = self.batches.with(|data| {
+            let id = data
+                .borrow()
+                .last_key_value()
+                .map(|(k, _)| k + 1)
+                .unwrap_or(1);
+            data.borrow_mut().insert(id, batch.clone());
+            id
+        });
+        self.items.with(|data| {
+            for (index, item) in items.iter().enumerate() {
+                data.borrow_mut().insert((id, index as u64), item.clone());
+            }
+        });
+
+        Ok((id, batch.to_response(id, items)))
+    }
+
+    pub fn get_batch(&self, id: u64) -> CanisterResult<PayoutBatchResponse> {
+        self.batches
+            .with(|data| data.borrow().get(&id))
+            .map(|batch| batch.to_response(id, self.get_items(id)))
+            .ok_or_else(|| {
+                ApiError::not_found("Payout batch not found")
+                    .add_method_name("get_batch")
+                    .add_info(id)
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    pub fn get_batches_by_status(&self, status: PayoutBatchStatus) -> Vec<PayoutBatchResponse> {
+        let batches: Vec<(u64, PayoutBatch)> = self.batches.with(|data| {
+            data.borrow()
+                .iter()
+                .filter(|(_, batch)| batch.status == status)
+                .collect()
+        });
+
+        batches
+            .into_iter()
+            .map(|(id, batch)| batch.to_response(id, self.get_items(id)))
+            .collect()
+    }
+
+    pub fn get_items(&self, id: u64) -> Vec<PayoutItem> {
+        self.items.with(|data| {
+            data.borrow()
+                .range((id, 0)..=(id, u64::MAX))
+                .map(|(_, item)| item)
+                .collect()
+        })
+    }
+
+    /// Execute up to `chunk_size` pending payouts of the oldest pending batches
+    /// # Returns
+    /// * `PayoutChunkResult` - `Busy` while a previous chunk is running, `Done` when there is nothing left to do
+    pub async fn process_next_chunk(&self) -> PayoutChunkResult {
+        let Some(_guard) = ReentrancyGuard::acquire(&PROCESSING) else {
+            return PayoutChunkResult::Busy;
+        };
+
+        let pending_batches: Vec<(u64, PayoutBatch)> = self.batches.with(|data| {
+            data.borrow()
+                .iter()
+                .filter(|(_, batch)| batch.status == PayoutBatchStatus::Pending)
+                .collect()
+        });
+
+        let mut work: Vec<(u64, u64)> = vec![];
+        for (id, mut batch) in pending_batches {
+            if work.len() >= self.chunk_size {
+                break;
+            }
+
+            // the scan starts at the first item that may still be pending
+            let indexes: Vec<u64> = self.items.with(|data| {
+                data.borrow()
+                    .range((id, batch.next_pending_index)..=(id, u64::MAX))
+                    .filter(|(_, item)| item.is_pending())
+                    .map(|((_, index), _)| index)
+                    .take(self.chunk_size - work.len())
+                    .collect()
+            });
+
+            if let Some(first) = indexes.first() {
+                if *first > batch.next_pending_index {
+                    batch.next_pending_index = *first;
+                    self.batches
+                        .with(|data| data.borrow_mut().insert(id, batch));
+                }
+            }
+            work.extend(indexes.into_iter().map(|index| (id, index)));
+        }
+
+        if work.is_empty() {
+            return PayoutChunkResult::Done;
+        }
+
+        for (id, index) in work.iter() {
+            self.execute(*id, *index).await;
+        }
+
+        PayoutChunkResult::Processed(work.len() as u64)
+    }
+
+    async fn execute(&self, id: u64, index: u64) {
+        let Some(batch) = self.batches.with(|data| data.borrow().get(&id)) else {
+            return;
+        };
+        let Some(mut item) = self.items.with(|data| data.borrow().get(&(id, index))) else {
+            return;
+        };
+
+        // a retry after the window could pay twice, the ledger has to be checked manually
+        if item.is_outside_deduplication_window(time()) {
+            item.status = PayoutStatus::failed(
+                &ApiError::conflict(
+                    "Payout is outside the ledger deduplication window, verify the ledger before paying again",
+                )
+                .add_method_name("process_next_chunk")
+                .add_info(id)
+                .add_source("toolkit_utils"),
+            );
+            self.save_item(id, index, item);
+            return;
+        }
+
+        // the index keeps identical payouts attempted in the same message apart for the ledger deduplication
+        item.created_at_time = item
+            .created_at_time
+            .or_else(|| Some(time().saturating_add(index)));
+        item.attempts += 1;
+        let args = item.to_transfer_arg(batch.from_subaccount);
+        self.save_item(id, index, item.clone());
+
+        let result = TokenLedger::new(batch.ledger_canister_id)
+            .icrc1_transfer(args)
+            .await;
+
+        // reload, the item may have been updated during the call
+        let Some(mut item) = self.items.with(|data| data.borrow().get(&(id, index))) else {
+            return;
+        };
+
+        item.status = match result {
+            Ok(block_index) => PayoutStatus::Completed { block_index },
+            Err(err) => match err.error_type() {
+                ApiErrorType::Icrc1TransferError(TransferError::Duplicate { duplicate_of }) => {
+                    PayoutStatus::Completed {
+                        block_index: duplicate_of.clone(),
+                    }
+                }
+                _ if is_retryable(&err) && item.attempts < self.max_attempts => {
+                    PayoutStatus::Pending
+                }
+                _ => PayoutStatus::failed(&err),
+            },
+        };
+
+        self.save_item(id, index, item);
+    }
+
+    // only a pending item that settles changes the counts of its batch
+    fn save_item(&self, id: u64, index: u64, item: PayoutItem) {
+        let was_pending = self
+            .items
+            .with(|data| data.borrow_mut().insert((id, index), item.clone()))
+            .is_some_and(|previous| previous.is_pending());
+
+        if was_pending && !item.is_pending() {
+            self.batches.with(|data| {
+                let batch = data.borrow().get(&id);
+                if let Some(mut batch) = batch {
+                    batch.settle_item(&item.status);
+                    data.borrow_mut().insert(id, batch);
+                }
+            });
+        }
+    }
+}
+
+fn is_retryable(err: &ApiError) -> bool {
+    match err.error_type() {
+        ApiErrorType::CanisterCallError(err) => err.is_retryable_with_deduplication(),
+        ApiErrorType::Icrc1TransferError(err) => matches!(
+            err,
+            TransferError::TemporarilyUnavailable | TransferError::CreatedInFuture { .. }
+        ),
+        _ => false,
+    }
+}
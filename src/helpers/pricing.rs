@@ -0,0 +1,385 @@
+use crate::{
+    api_error::ApiError,
+    cell::{CellStorage, StaticCellStorageRef},
+    cell_storage::GenericCellStorage,
+    consumed_payment::ConsumedPayment,
+    misc::{generic::CANISTER_SPINUP_CYCLES, runtime::time},
+    network_config::NetworkConfig,
+    pricing_quote::{DeploymentPlan, PricingQuote, QuoteLineItem, BASE_SUBNET_SIZE},
+    result::CanisterResult,
+    token_amount::{RoundingMode, TokenAmount, XDR_PERMYRIAD_DECIMALS},
+    StaticStorageRef,
+};
+
+use super::{network::network_config, rate_provider::conversion_rate_on};
+
+pub static DEFAULT_QUOTE_VALIDITY_SECONDS: u64 = 60 * 15; // 15 minutes
+
+/// Prices a `DeploymentPlan` in cycles, XDR and ICP with integer math only and stores the quote,
+/// so a payment can be checked against the exact quote it was made for.
+/// Quote ids come from a stable counter and are never reused, also not after `remove_expired`.
+#[derive(Clone, Copy)]
+pub struct PricingCalculator {
+    quotes: StaticStorageRef<u64, PricingQuote>,
+    last_quote_id: StaticCellStorageRef<u64>,
+    validity_seconds: u64,
+}
+
+impl PricingCalculator {
+    pub fn new(
+        quotes: StaticStorageRef<u64, PricingQuote>,
+        last_quote_id: StaticCellStorageRef<u64>,
+    ) -> Self {
+        Self {
+            quotes,
+            last_quote_id,
+            validity_seconds: DEFAULT_QUOTE_VALIDITY_SECONDS,
+        }
+    }
+
+    pub fn with_validity_seconds(mut self, validity_seconds: u64) -> Self {
+        self.validity_seconds = validity_seconds;
+        self
+    }
+
+    /// Price a plan at the current ICP/XDR rate and store the quote
+    /// # Returns
+    /// * `Result<(u64, PricingQuote), ApiError>` - The quote id and the quote
+    pub async fn quote(&self, plan: DeploymentPlan) -> CanisterResult<(u64, PricingQuote)> {
+        self.quote_on(&network_config(), plan).await
+    }
+
+    pub async fn quote_on(
+        &self,
+        network: &NetworkConfig,
+        plan: DeploymentPlan,
+    ) -> CanisterResult<(u64, PricingQuote)> {
+        let rate = conversion_rate_on(network)
+            .await
+            .map_err(|err| err.add_method_name("quote"))?;
+
+        let mut quote = price_plan(&plan, rate.xdr_permyriad_per_icp)?;
+        quote.rate_timestamp_seconds = rate.timestamp_seconds;
+        quote.expires_at = quote
+            .created_at
+            .saturating_add(self.validity_seconds.saturating_mul(1_000_000_000));
+
+        let last_quote_id = GenericCellStorage::new("last_quote_id", self.last_quote_id);
+        let id = last_quote_id
+            .set(last_quote_id.get().unwrap_or(0) + 1)
+            .map_err(|err| err.add_method_name("quote"))?;
+        self.quotes
+            .with(|data| data.borrow_mut().insert(id, quote.clone()));
+
+        Ok((id, quote))
+    }
+
+    pub fn get_quote(&self, id: u64) -> CanisterResult<PricingQuote> {
+        self.quotes
+            .with(|data| data.borrow().get(&id))
+            .ok_or_else(|| {
+                ApiError::not_found("Quote not found")
+                    .add_method_name("get_quote")
+                    .add_info(id)
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    /// Settle a stored quote with a payment verified by `verify_payment`, made with the quote id as memo.
+    /// The memo binds the payment to this quote, so one payment can not settle several quotes,
+    /// and a quote can only be paid once.
+    /// # Returns
+    /// * `Result<PricingQuote, ApiError>` - The paid quote
+    pub fn check_payment(
+        &self,
+        id: u64,
+        payment: &ConsumedPayment,
+    ) -> CanisterResult<PricingQuote> {
+        let mut quote = self
+            .get_quote(id)
+            .map_err(|err| err.add_method_name("check_payment"))?;
+
+        if let Some(block_index) = quote.paid_block_index {
+            return Err(ApiError::duplicate(&format!(
+                "Quote is already paid in block {}",
+                block_index
+            ))
+            .add_method_name("check_payment")
+            .add_info(id)
+            .add_source("toolkit_utils"));
+        }
+
+        if payment.memo != id {
+            return Err(ApiError::bad_request(&format!(
+                "Payment memo {} does not match the quote",
+                payment.memo
+            ))
+            .add_method_name("check_payment")
+            .add_info(id)
+            .add_source("toolkit_utils"));
+        }
+
+        if quote.is_expired(payment.block_timestamp) {
+            return Err(ApiError::conflict("Quote expired before the payment")
+                .add_method_name("check_payment")
+                .add_info(id)
+                .add_source("toolkit_utils"));
+        }
+
+        if payment.amount_e8s < quote.total_icp_e8s {
+            return Err(ApiError::bad_request(&format!(
+                "Payment of {} e8s is below the quoted {} e8s",
+                payment.amount_e8s, quote.total_icp_e8s
+            ))
+            .add_method_name("check_payment")
+            .add_info(id)
+            .add_source("toolkit_utils"));
+        }
+
+        quote.paid_block_index = Some(payment.block_index);
+        self.quotes
+            .with(|data| data.borrow_mut().insert(id, quote.clone()));
+        Ok(quote)
+    }
+
+    /// Remove unpaid quotes that expired before `before`
+    pub fn remove_expired(&self, before: u64) -> u64 {
+        self.quotes.with(|data| {
+            let expired: Vec<u64> = data
+                .borrow()
+                .iter()
+                .filter(|(_, quote)| !quote.is_paid() && quote.is_expired(before))
+                .map(|(id, _)| id)
+                .collect();
+            for id in &expired {
+                data.borrow_mut().remove(id);
+            }
+            expired.len() as u64
+        })
+    }
+}
+
+/// Price a plan at a given rate, the quote expires immediately and is not stored
+pub fn price_plan(
+    plan: &DeploymentPlan,
+    xdr_permyriad_per_icp: u64,
+) -> CanisterResult<PricingQuote> {
+    if plan.subnet_size == 0 {
+        return Err(
+            ApiError::bad_request("Subnet size must be greater than zero")
+                .add_method_name("price_plan")
+                .add_source("toolkit_utils"),
+        );
+    }
+    if xdr_permyriad_per_icp == 0 {
+        return Err(
+            ApiError::bad_request("Conversion rate must be greater than zero")
+                .add_method_name("price_plan")
+                .add_source("toolkit_utils"),
+        );
+    }
+
+    // the creation fee scales linearly with the number of nodes of the subnet
+    let creation_fee = TokenAmount::from_cycles(CANISTER_SPINUP_CYCLES as u128).checked_mul_div(
+        plan.subnet_size as u128,
+        BASE_SUBNET_SIZE as u128,
+        RoundingMode::Up,
+    )?;
+
+    let items = vec![
+        cycles_item(
+            "Canister creation",
+            plan.canister_count,
+            creation_fee.checked_mul(plan.canister_count as u128)?,
+            xdr_permyriad_per_icp,
+        )?,
+        cycles_item(
+            "Initial cycles",
+            plan.canister_count,
+            TokenAmount::from_cycles(plan.initial_cycles_per_canister)
+                .checked_mul(plan.canister_count as u128)?,
+            xdr_permyriad_per_icp,
+        )?,
+        cycles_item(
+            "Service fee",
+            1,
+            TokenAmount::from_xdr_permyriad(plan.service_fee_xdr_permyriad).xdr_to_cycles()?,
+            xdr_permyriad_per_icp,
+        )?,
+    ];
+
+    let overflow = || {
+        ApiError::bad_request("Quote total overflows")
+            .add_method_name("price_plan")
+            .add_source("toolkit_utils")
+    };
+
+    let mut total_cycles: u128 = 0;
+    let mut total_xdr_permyriad: u64 = 0;
+    let mut total_icp_e8s: u64 = 0;
+    for item in &items {
+        total_cycles = total_cycles.checked_add(item.cycles).ok_or_else(overflow)?;
+        total_xdr_permyriad = total_xdr_permyriad
+            .checked_add(item.xdr_permyriad)
+            .ok_or_else(overflow)?;
+        total_icp_e8s = total_icp_e8s
+            .checked_add(item.icp_e8s)
+            .ok_or_else(overflow)?;
+    }
+
+    let created_at = time();
+    Ok(PricingQuote {
+        plan: plan.clone(),
+        items,
+        total_cycles,
+        total_xdr_permyriad,
+        total_icp_e8s,
+        xdr_permyriad_per_icp,
+        rate_timestamp_seconds: 0,
+        created_at,
+        expires_at: created_at,
+        paid_block_index: None,
+    })
+}
+
+// 1 XDR converts to 1T cycles, so XDR with 12 decimals are cycles
+fn cycles_item(
+    label: &str,
+    quantity: u64,
+    cycles: TokenAmount,
+    xdr_permyriad_per_icp: u64,
+) -> CanisterResult<QuoteLineItem> {
+    Ok(QuoteLineItem {
+        label: label.to_string(),
+        quantity,
+        cycles: cycles.units(),
+        xdr_permyriad: cycles
+            .rescale(XDR_PERMYRIAD_DECIMALS, RoundingMode::Up)?
+            .to_u64()?,
+        icp_e8s: cycles
+            .cycles_to_icp(xdr_permyriad_per_icp, RoundingMode::Up)?
+            .to_u64()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use candid::Principal;
+    use ic_ledger_types::{AccountIdentifier, DEFAULT_SUBACCOUNT};
+    use ic_stable_structures::{
+        memory_manager::{MemoryId, MemoryManager},
+        Cell, DefaultMemoryImpl, StableBTreeMap,
+    };
+
+    use super::*;
+    use crate::{
+        cell::CellStorageRef,
+        consumed_payment::ConsumedPayment,
+        misc::{generic::MIN_CYCLES_FOR_PROJECT_SPINUP, runtime::MockRuntime},
+        MemoryManagerStorage, StorageRef,
+    };
+
+    thread_local! {
+        static MEMORY_MANAGER: MemoryManagerStorage =
+            RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+        static QUOTES: StorageRef<u64, PricingQuote> = RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|manager| manager.borrow().get(MemoryId::new(0))),
+        ));
+        static LAST_QUOTE_ID: CellStorageRef<u64> = RefCell::new(
+            Cell::init(
+                MEMORY_MANAGER.with(|manager| manager.borrow().get(MemoryId::new(1))),
+                None,
+            )
+            .expect("failed to init the quote id cell"),
+        );
+    }
+
+    fn payment(
+        block_index: u64,
+        memo: u64,
+        amount_e8s: u64,
+        block_timestamp: u64,
+    ) -> ConsumedPayment {
+        let account = AccountIdentifier::new(&Principal::anonymous(), &DEFAULT_SUBACCOUNT);
+        ConsumedPayment::new(
+            block_index,
+            account,
+            account,
+            amount_e8s,
+            memo,
+            block_timestamp,
+        )
+    }
+
+    fn store_quotes(expires_at: u64) -> PricingQuote {
+        let mut quote = price_plan(&DeploymentPlan::canister(), 40_000).unwrap();
+        quote.expires_at = expires_at;
+        QUOTES.with(|data| {
+            data.borrow_mut().insert(1, quote.clone());
+            data.borrow_mut().insert(2, quote.clone());
+        });
+        quote
+    }
+
+    #[test]
+    fn price_plan_matches_the_spinup_constants() {
+        MockRuntime::new().install();
+
+        // 4 XDR per ICP
+        let quote = price_plan(&DeploymentPlan::project(), 40_000).unwrap();
+
+        assert_eq!(
+            quote.total_cycles,
+            MIN_CYCLES_FOR_PROJECT_SPINUP as u128 + 10_000_000_000_000
+        );
+        assert_eq!(quote.total_xdr_permyriad, 310_000);
+        assert_eq!(quote.total_icp_e8s, 775_000_000);
+    }
+
+    #[test]
+    fn a_quote_is_paid_once_and_only_before_it_expires() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+        runtime.set_time(1_000);
+
+        let calculator = PricingCalculator::new(&QUOTES, &LAST_QUOTE_ID);
+        let quote = store_quotes(2_000);
+        let price = quote.total_icp_e8s;
+
+        assert!(calculator
+            .check_payment(1, &payment(10, 1, price - 1, 1_500))
+            .is_err());
+        assert!(calculator
+            .check_payment(1, &payment(10, 1, price, 2_001))
+            .is_err());
+
+        let paid = calculator
+            .check_payment(1, &payment(10, 1, price, 2_000))
+            .unwrap();
+        assert_eq!(paid.paid_block_index, Some(10));
+        assert!(calculator
+            .check_payment(1, &payment(11, 1, price, 1_500))
+            .is_err());
+
+        runtime.advance_time(10_000);
+        assert_eq!(calculator.remove_expired(time()), 1);
+        assert!(calculator.get_quote(1).is_ok());
+        assert!(calculator.get_quote(2).is_err());
+    }
+
+    #[test]
+    fn a_payment_only_settles_the_quote_of_its_memo() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+
+        let calculator = PricingCalculator::new(&QUOTES, &LAST_QUOTE_ID);
+        let quote = store_quotes(2_000);
+        let payment = payment(10, 1, quote.total_icp_e8s, 1_000);
+
+        assert!(calculator.check_payment(1, &payment).is_ok());
+        assert!(calculator.check_payment(2, &payment).is_err());
+        assert!(!calculator.get_quote(2).unwrap().is_paid());
+    }
+}
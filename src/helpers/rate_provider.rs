@@ -0,0 +1,232 @@
+use std::cell::RefCell;
+
+use candid::{Decode, Principal};
+use ic_cdk::api::in_replicated_execution;
+use ic_certification::{Certificate, HashTree, LookupResult};
+
+use crate::{
+    api_error::ApiError,
+    cached_rate::CachedRate,
+    cycles_minting::{CyclesMintingService, IcpXdrConversionRate, IcpXdrConversionRateResponse},
+    misc::runtime::time,
+    network_config::NetworkConfig,
+    result::CanisterResult,
+};
+
+use super::network::network_config;
+
+pub static DEFAULT_RATE_MAX_AGE_SECONDS: u64 = 60 * 10; // 10 minutes
+
+static LABEL_ICP_XDR_CONVERSION_RATE: &[u8] = b"ICP_XDR_CONVERSION_RATE";
+static IC_STATE_ROOT_DOMAIN_SEPARATOR: &[u8] = b"\x0Dic-state-root";
+// DER prefix of an IC BLS12-381 public key, followed by the 96 byte raw key
+const DER_PREFIX: [u8; 37] = [
+    0x30, 0x81, 0x82, 0x30, 0x1d, 0x06, 0x0d, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05,
+    0x03, 0x01, 0x02, 0x01, 0x06, 0x0c, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05, 0x03,
+    0x02, 0x01, 0x03, 0x61, 0x00,
+];
+const BLS_PUBLIC_KEY_LENGTH: usize = 96;
+
+/// Verifies a BLS signature, e.g. `ic_verify_bls_signature::verify_bls_signature`
+pub type BlsSignatureVerifier =
+    fn(signature: &[u8], message: &[u8], public_key: &[u8]) -> Result<(), String>;
+
+/// Verification of the certified rate when the rate is fetched outside of replicated execution.
+#[derive(Clone)]
+pub struct CertificateVerifier {
+    /// The IC root key, raw or DER encoded
+    pub root_key: Vec<u8>,
+    pub verify_signature: BlsSignatureVerifier,
+}
+
+#[derive(Clone)]
+pub struct RateProviderConfig {
+    pub max_age_seconds: u64,
+    pub verifier: Option<CertificateVerifier>,
+}
+
+impl Default for RateProviderConfig {
+    fn default() -> Self {
+        Self {
+            max_age_seconds: DEFAULT_RATE_MAX_AGE_SECONDS,
+            verifier: None,
+        }
+    }
+}
+
+thread_local! {
+    static RATE_PROVIDER_CONFIG: RefCell<RateProviderConfig> = RefCell::new(RateProviderConfig::default());
+    static RATE_CACHE: RefCell<Option<CachedRate>> = const { RefCell::new(None) };
+}
+
+/// Configure the max age of the cached rate and the certificate verification, call this in `init` and `post_upgrade`.
+pub fn configure_rate_provider(config: RateProviderConfig) {
+    RATE_PROVIDER_CONFIG.with(|current| *current.borrow_mut() = config);
+}
+
+/// The cached rate, without fetching a new one
+pub fn cached_conversion_rate() -> Option<CachedRate> {
+    RATE_CACHE.with(|cache| cache.borrow().clone())
+}
+
+pub fn clear_conversion_rate_cache() {
+    RATE_CACHE.with(|cache| *cache.borrow_mut() = None);
+}
+
+/// The ICP/XDR conversion rate of the CMC, fetched when the cached rate is older than the max age.
+///
+/// In a query context the certificate is verified when a `CertificateVerifier` is configured,
+/// in replicated execution the response of the CMC is trusted.
+pub async fn conversion_rate() -> CanisterResult<CachedRate> {
+    conversion_rate_on(&network_config()).await
+}
+
+pub async fn conversion_rate_on(network: &NetworkConfig) -> CanisterResult<CachedRate> {
+    let config = RATE_PROVIDER_CONFIG.with(|config| config.borrow().clone());
+
+    if let Some(cached) = cached_conversion_rate() {
+        let age_seconds = time().saturating_sub(cached.fetched_at) / 1_000_000_000;
+        if cached.cycles_minting_canister_id == network.cycles_minting_canister_id
+            && age_seconds <= config.max_age_seconds
+        {
+            return Ok(cached);
+        }
+    }
+
+    let response = CyclesMintingService(network.cycles_minting_canister_id)
+        .get_icp_xdr_conversion_rate()
+        .await
+        .map(|(rate,)| rate)
+        .map_err(|err| {
+            ApiError::from(err)
+                .with_context("Error getting XDR conversion rate")
+                .add_method_name("conversion_rate")
+                .add_source("toolkit_utils")
+        })?;
+
+    let certified = match (&config.verifier, in_replicated_execution()) {
+        (Some(verifier), false) => {
+            verify_conversion_rate_response(
+                &response,
+                network.cycles_minting_canister_id,
+                verifier,
+                config.max_age_seconds,
+            )?;
+            true
+        }
+        _ => false,
+    };
+
+    let rate = CachedRate {
+        cycles_minting_canister_id: network.cycles_minting_canister_id,
+        xdr_permyriad_per_icp: response.data.xdr_permyriad_per_icp,
+        timestamp_seconds: response.data.timestamp_seconds,
+        fetched_at: time(),
+        certified,
+    };
+
+    RATE_CACHE.with(|cache| *cache.borrow_mut() = Some(rate.clone()));
+    Ok(rate)
+}
+
+/// Verify that the rate in the response is certified by the CMC, signed with the IC root key
+/// and that the certificate is not older than `max_age_seconds`
+pub fn verify_conversion_rate_response(
+    response: &IcpXdrConversionRateResponse,
+    cycles_minting_canister_id: Principal,
+    verifier: &CertificateVerifier,
+    max_age_seconds: u64,
+) -> CanisterResult<()> {
+    let invalid = |message: &str| {
+        ApiError::unauthorized(message)
+            .add_method_name("verify_conversion_rate_response")
+            .add_source("toolkit_utils")
+    };
+
+    let certificate: Certificate = serde_cbor::from_slice(&response.certificate)
+        .map_err(|_| invalid("Invalid certificate encoding"))?;
+    let hash_tree: HashTree = serde_cbor::from_slice(&response.hash_tree)
+        .map_err(|_| invalid("Invalid hash tree encoding"))?;
+
+    // the CMC lives on the NNS subnet, which signs with the root key without a delegation
+    if certificate.delegation.is_some() {
+        return Err(invalid(
+            "Delegated certificates are not accepted for the CMC",
+        ));
+    }
+
+    let root_key = match verifier.root_key.len() {
+        BLS_PUBLIC_KEY_LENGTH => verifier.root_key.as_slice(),
+        len if len == DER_PREFIX.len() + BLS_PUBLIC_KEY_LENGTH
+            && verifier.root_key.starts_with(&DER_PREFIX) =>
+        {
+            &verifier.root_key[DER_PREFIX.len()..]
+        }
+        _ => return Err(invalid("Invalid root key")),
+    };
+
+    let mut message = IC_STATE_ROOT_DOMAIN_SEPARATOR.to_vec();
+    message.extend_from_slice(&certificate.tree.digest());
+    (verifier.verify_signature)(&certificate.signature, &message, root_key)
+        .map_err(|err| invalid(&format!("Invalid certificate signature: {}", err)))?;
+
+    // a validly signed but old certificate could be replayed with a stale rate
+    let certificate_time = match certificate.tree.lookup_path([b"time".as_slice()]) {
+        LookupResult::Found(data) => {
+            decode_leb128(data).ok_or_else(|| invalid("Invalid certificate time encoding"))?
+        }
+        _ => return Err(invalid("Certificate does not contain the time")),
+    };
+    if time().saturating_sub(certificate_time) > max_age_seconds.saturating_mul(1_000_000_000) {
+        return Err(invalid("Certificate is too old"));
+    }
+
+    let certified_data = match certificate.tree.lookup_path([
+        b"canister".as_slice(),
+        cycles_minting_canister_id.as_slice(),
+        b"certified_data".as_slice(),
+    ]) {
+        LookupResult::Found(data) => data,
+        _ => {
+            return Err(invalid(
+                "Certificate does not contain the certified data of the CMC",
+            ))
+        }
+    };
+
+    if certified_data != hash_tree.digest().as_slice() {
+        return Err(invalid("Hash tree does not match the certified data"));
+    }
+
+    let rate = match hash_tree.lookup_path([LABEL_ICP_XDR_CONVERSION_RATE]) {
+        LookupResult::Found(data) => Decode!(data, IcpXdrConversionRate)
+            .map_err(|_| invalid("Invalid certified conversion rate encoding"))?,
+        _ => return Err(invalid("Hash tree does not contain the conversion rate")),
+    };
+
+    if rate.xdr_permyriad_per_icp != response.data.xdr_permyriad_per_icp
+        || rate.timestamp_seconds != response.data.timestamp_seconds
+    {
+        return Err(invalid(
+            "Certified conversion rate does not match the response",
+        ));
+    }
+
+    Ok(())
+}
+
+// unsigned LEB128, as used for the `time` leaf of a certificate
+fn decode_leb128(bytes: &[u8]) -> Option<u64> {
+    let mut value: u64 = 0;
+    for (index, byte) in bytes.iter().enumerate() {
+        let shift = 7 * index as u32;
+        if shift >= 64 {
+            return None;
+        }
+        value |= u64::from(byte & 0x7f).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return (index + 1 == bytes.len()).then_some(value);
+        }
+    }
+    None
+}
@@ -0,0 +1,119 @@
+use std::cell::RefCell;
+
+use candid::Principal;
+use ic_ledger_types::{AccountIdentifier, Subaccount};
+use icrc_ledger_types::icrc1::account::Account;
+
+use crate::{
+    api_error::ApiError,
+    misc::{hash::generate_domain_hash, runtime::canister_self},
+    result::CanisterResult,
+    subaccount_entry::SubaccountEntry,
+    StaticStorageRef,
+};
+
+// domains used by the helpers of this crate
+pub static SUBACCOUNT_DOMAIN_DEPOSIT: &str = "deposit";
+pub static SUBACCOUNT_DOMAIN_TOP_UP: &str = "top-up";
+pub static SUBACCOUNT_DOMAIN_APPROVE: &str = "approve";
+pub static SUBACCOUNT_DOMAIN_SPINUP: &str = "spinup";
+pub static SUBACCOUNT_DOMAIN_ESCROW: &str = "escrow";
+
+thread_local! {
+    static SUBACCOUNT_REGISTRY_STORAGE: RefCell<Option<StaticStorageRef<[u8; 32], SubaccountEntry>>> = const { RefCell::new(None) };
+}
+
+/// Register the map that holds the derived subaccounts, call this in `init` and `post_upgrade`.
+pub fn register_subaccount_registry_storage(storage: StaticStorageRef<[u8; 32], SubaccountEntry>) {
+    SUBACCOUNT_REGISTRY_STORAGE.with(|current| *current.borrow_mut() = Some(storage));
+}
+
+/// Derive a subaccount from a domain tag, principal and nonce, e.g. `("deposit", user, order_id)`
+pub fn derive_subaccount(domain: &str, principal: Principal, nonce: u64) -> Subaccount {
+    Subaccount(generate_domain_hash(
+        domain,
+        &[principal.as_slice(), &nonce.to_be_bytes()],
+    ))
+}
+
+/// The ICRC-1 account of this canister for the derived subaccount
+pub fn derive_account(domain: &str, principal: Principal, nonce: u64) -> Account {
+    Account {
+        owner: canister_self(),
+        subaccount: Some(derive_subaccount(domain, principal, nonce).0),
+    }
+}
+
+/// The ICP account identifier of this canister for the derived subaccount
+pub fn derive_account_identifier(
+    domain: &str,
+    principal: Principal,
+    nonce: u64,
+) -> AccountIdentifier {
+    AccountIdentifier::new(
+        &canister_self(),
+        &derive_subaccount(domain, principal, nonce),
+    )
+}
+
+/// Derive a subaccount and store it for reverse lookup, registering the same inputs twice is a no-op
+/// # Returns
+/// * `Result<Subaccount, ApiError>` - The derived subaccount
+pub fn register_subaccount(
+    domain: &str,
+    principal: Principal,
+    nonce: u64,
+) -> CanisterResult<Subaccount> {
+    let subaccount = derive_subaccount(domain, principal, nonce);
+
+    storage("register_subaccount")?.with(|data| {
+        if !data.borrow().contains_key(&subaccount.0) {
+            data.borrow_mut()
+                .insert(subaccount.0, SubaccountEntry::new(domain, principal, nonce));
+        }
+    });
+
+    Ok(subaccount)
+}
+
+/// Find the domain, principal and nonce a subaccount was derived from
+pub fn lookup_subaccount(subaccount: &Subaccount) -> CanisterResult<SubaccountEntry> {
+    storage("lookup_subaccount")?
+        .with(|data| data.borrow().get(&subaccount.0))
+        .ok_or_else(|| {
+            ApiError::not_found("Subaccount not registered")
+                .add_method_name("lookup_subaccount")
+                .add_info(
+                    subaccount
+                        .0
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect::<String>(),
+                )
+                .add_source("toolkit_utils")
+        })
+}
+
+/// All registered subaccounts of a principal within a domain
+pub fn get_subaccounts_by_principal(
+    domain: &str,
+    principal: Principal,
+) -> CanisterResult<Vec<(Subaccount, SubaccountEntry)>> {
+    Ok(storage("get_subaccounts_by_principal")?.with(|data| {
+        data.borrow()
+            .iter()
+            .filter(|(_, entry)| entry.domain == domain && entry.principal == principal)
+            .map(|(key, entry)| (Subaccount(key), entry))
+            .collect()
+    }))
+}
+
+fn storage(method_name: &str) -> CanisterResult<StaticStorageRef<[u8; 32], SubaccountEntry>> {
+    SUBACCOUNT_REGISTRY_STORAGE
+        .with(|current| *current.borrow())
+        .ok_or_else(|| {
+            ApiError::unexpected("Subaccount registry storage is not registered")
+                .add_method_name(method_name)
+                .add_source("toolkit_utils")
+        })
+}
@@ -1,12 +1,11 @@
+use std::{cell::RefCell, collections::HashMap};
+
 use candid::{Nat, Principal};
-use ic_cdk::{
-    api::{canister_self, time},
-    call::Call,
-};
+use ic_cdk::call::Call;
 use ic_ledger_types::{
     account_balance, query_archived_blocks, query_blocks, transfer, AccountBalanceArgs,
     AccountIdentifier, Block, BlockIndex, GetBlocksArgs, Operation, Subaccount, Timestamp, Tokens,
-    TransferArgs, MAINNET_CYCLES_MINTING_CANISTER_ID, MAINNET_LEDGER_CANISTER_ID,
+    Transaction, TransferArgs,
 };
 use icrc_ledger_types::{
     icrc1::{account::Account, transfer::Memo},
@@ -20,17 +19,97 @@ use icrc_ledger_types::{
 
 use crate::{
     api_error::ApiError,
+    consumed_payment::ConsumedPayment,
     cycles_minting::{
-        CyclesMintingService, NotifyCreateCanisterArg, NotifyCreateCanisterResult, NotifyError,
-        NotifyTopUpArg, NotifyTopUpResult,
+        CyclesMintingService, NotifyCreateCanisterArg, NotifyCreateCanisterResult, NotifyTopUpArg,
+        NotifyTopUpResult,
     },
-    misc::generic::{ICP_TRANSACTION_FEE, MEMO_TOP_UP_CANISTER},
-    result::CanisterResult,
+    misc::{
+        generic::MEMO_TOP_UP_CANISTER,
+        runtime::{canister_self, time},
+    },
+    network_config::NetworkConfig,
+    result::{CanisterResult, ErrorContext},
+    token_ledger::TokenLedger,
+    transfer_plan::{FeePayer, TransferPlan},
+};
+
+use super::{
+    consumed_payments::{consume_payment, is_payment_consumed},
+    conversions::try_nat_to_u64,
+    misc::principal_to_account_identifier,
+    network::network_config,
 };
 
-use super::misc::{nat_to_u64, principal_to_account_identifier};
+pub static DEFAULT_FEE_MAX_AGE_SECONDS: u64 = 60 * 60; // 1 hour
+
+thread_local! {
+    // ledger canister id -> (fee in e8s, fetched at in nanoseconds)
+    static FEE_CACHE: RefCell<HashMap<Principal, (u64, u64)>> = RefCell::new(HashMap::new());
+}
+
+/// The current transfer fee of the ICP ledger, cached for `DEFAULT_FEE_MAX_AGE_SECONDS`
+pub async fn icp_transfer_fee() -> CanisterResult<u64> {
+    icp_transfer_fee_on(&network_config()).await
+}
+
+pub async fn icp_transfer_fee_on(network: &NetworkConfig) -> CanisterResult<u64> {
+    let now = time();
+    let cached = FEE_CACHE.with(|cache| cache.borrow().get(&network.ledger_canister_id).copied());
+    if let Some((fee_e8s, fetched_at)) = cached {
+        if now.saturating_sub(fetched_at)
+            < DEFAULT_FEE_MAX_AGE_SECONDS.saturating_mul(1_000_000_000)
+        {
+            return Ok(fee_e8s);
+        }
+    }
+
+    let fee = TokenLedger::new(network.ledger_canister_id)
+        .icrc1_fee()
+        .await?;
+    let fee_e8s = try_nat_to_u64(&fee).map_err(|err| err.add_method_name("icp_transfer_fee"))?;
+
+    FEE_CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .insert(network.ledger_canister_id, (fee_e8s, now))
+    });
+    Ok(fee_e8s)
+}
+
+/// Drop the cached transfer fees, e.g. after a transfer was rejected with `BadFee`
+pub fn clear_icp_transfer_fee_cache() {
+    FEE_CACHE.with(|cache| cache.borrow_mut().clear());
+}
+
+/// Plan an ICP transfer of `transfers` consecutive ledger transfers with the current ledger fee
+pub async fn plan_icp_transfer(
+    amount_e8s: u64,
+    fee_payer: FeePayer,
+    transfers: u64,
+) -> CanisterResult<TransferPlan> {
+    plan_icp_transfer_on(&network_config(), amount_e8s, fee_payer, transfers).await
+}
+
+pub async fn plan_icp_transfer_on(
+    network: &NetworkConfig,
+    amount_e8s: u64,
+    fee_payer: FeePayer,
+    transfers: u64,
+) -> CanisterResult<TransferPlan> {
+    let fee_e8s = icp_transfer_fee_on(network).await?;
+    TransferPlan::new(amount_e8s, fee_e8s, fee_payer, transfers)
+}
 
 pub async fn transfer_icp(to: Principal, amount_e8s: u64) -> CanisterResult<BlockIndex> {
+    transfer_icp_on(&network_config(), to, amount_e8s).await
+}
+
+pub async fn transfer_icp_on(
+    network: &NetworkConfig,
+    to: Principal,
+    amount_e8s: u64,
+) -> CanisterResult<BlockIndex> {
     let args = TransferArgs {
         to: principal_to_account_identifier(to),
         amount: Tokens::from_e8s(amount_e8s),
@@ -42,14 +121,12 @@ pub async fn transfer_icp(to: Principal, amount_e8s: u64) -> CanisterResult<Bloc
         }),
     };
 
-    match transfer(MAINNET_LEDGER_CANISTER_ID, &args).await {
-        Ok(result) => match result {
-            Ok(block_index) => Ok(block_index),
-            Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
-                .add_method_name("transfer_icp")
-                .add_source("toolkit_utils")),
-        },
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+    match transfer(network.ledger_canister_id, &args).await {
+        Ok(Ok(block_index)) => Ok(block_index),
+        Ok(Err(err)) => Err(ApiError::from(err)
+            .add_method_name("transfer_icp")
+            .add_source("toolkit_utils")),
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("transfer_icp")
             .add_source("toolkit_utils")),
     }
@@ -59,6 +136,15 @@ pub async fn transfer_icp_from_subaccount(
     to: Principal,
     principal: Principal,
     amount_e8s: u64,
+) -> CanisterResult<BlockIndex> {
+    transfer_icp_from_subaccount_on(&network_config(), to, principal, amount_e8s).await
+}
+
+pub async fn transfer_icp_from_subaccount_on(
+    network: &NetworkConfig,
+    to: Principal,
+    principal: Principal,
+    amount_e8s: u64,
 ) -> CanisterResult<BlockIndex> {
     let args = TransferArgs {
         to: principal_to_account_identifier(to),
@@ -71,14 +157,12 @@ pub async fn transfer_icp_from_subaccount(
         }),
     };
 
-    match transfer(MAINNET_LEDGER_CANISTER_ID, &args).await {
-        Ok(result) => match result {
-            Ok(block_index) => Ok(block_index),
-            Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
-                .add_method_name("transfer_icp_from_subaccount")
-                .add_source("toolkit_utils")),
-        },
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+    match transfer(network.ledger_canister_id, &args).await {
+        Ok(Ok(block_index)) => Ok(block_index),
+        Ok(Err(err)) => Err(ApiError::from(err)
+            .add_method_name("transfer_icp_from_subaccount")
+            .add_source("toolkit_utils")),
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("transfer_icp_from_subaccount")
             .add_source("toolkit_utils")),
     }
@@ -87,6 +171,14 @@ pub async fn transfer_icp_from_subaccount(
 pub async fn transfer_icp_by_account_identifier(
     to: AccountIdentifier,
     amount_e8s: u64,
+) -> CanisterResult<BlockIndex> {
+    transfer_icp_by_account_identifier_on(&network_config(), to, amount_e8s).await
+}
+
+pub async fn transfer_icp_by_account_identifier_on(
+    network: &NetworkConfig,
+    to: AccountIdentifier,
+    amount_e8s: u64,
 ) -> CanisterResult<BlockIndex> {
     let args = TransferArgs {
         to,
@@ -99,14 +191,12 @@ pub async fn transfer_icp_by_account_identifier(
         }),
     };
 
-    match transfer(MAINNET_LEDGER_CANISTER_ID, &args).await {
-        Ok(result) => match result {
-            Ok(block_index) => Ok(block_index),
-            Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
-                .add_method_name("transfer_icp_by_account_identifier")
-                .add_source("toolkit_utils")),
-        },
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+    match transfer(network.ledger_canister_id, &args).await {
+        Ok(Ok(block_index)) => Ok(block_index),
+        Ok(Err(err)) => Err(ApiError::from(err)
+            .add_method_name("transfer_icp_by_account_identifier")
+            .add_source("toolkit_utils")),
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("transfer_icp_by_account_identifier")
             .add_source("toolkit_utils")),
     }
@@ -116,20 +206,47 @@ pub async fn top_up_cycles_and_notify(
     icp_amount: u64,
     principal: Principal,
 ) -> CanisterResult<Nat> {
-    let block_index = top_up_cycles(icp_amount, principal).await?;
-    notify_top_up_cycles(block_index).await
+    top_up_cycles_and_notify_on(&network_config(), icp_amount, principal).await
+}
+
+pub async fn top_up_cycles_and_notify_on(
+    network: &NetworkConfig,
+    icp_amount: u64,
+    principal: Principal,
+) -> CanisterResult<Nat> {
+    let block_index = top_up_cycles_on(network, icp_amount, principal)
+        .await
+        .with_context(
+            "Failed to transfer ICP to the CMC",
+            "top_up_cycles_and_notify",
+        )?;
+    notify_top_up_cycles_on(network, block_index)
+        .await
+        .with_context(
+            format!("Failed to notify the CMC about block {}", block_index),
+            "top_up_cycles_and_notify",
+        )
 }
 
 pub async fn top_up_cycles(icp_amount: u64, canister: Principal) -> CanisterResult<BlockIndex> {
-    let amount = Tokens::from_e8s(icp_amount - ICP_TRANSACTION_FEE);
+    top_up_cycles_on(&network_config(), icp_amount, canister).await
+}
+
+pub async fn top_up_cycles_on(
+    network: &NetworkConfig,
+    icp_amount: u64,
+    canister: Principal,
+) -> CanisterResult<BlockIndex> {
+    let plan = plan_icp_transfer_on(network, icp_amount, FeePayer::Recipient, 1).await?;
+    let amount = Tokens::from_e8s(plan.net_e8s);
 
     let args = TransferArgs {
         memo: ic_ledger_types::Memo(MEMO_TOP_UP_CANISTER),
         amount,
-        fee: Tokens::from_e8s(ICP_TRANSACTION_FEE),
+        fee: Tokens::from_e8s(plan.fee_e8s),
         from_subaccount: None,
         to: AccountIdentifier::new(
-            &MAINNET_CYCLES_MINTING_CANISTER_ID,
+            &network.cycles_minting_canister_id,
             &Subaccount::from(canister),
         ),
         created_at_time: Some(Timestamp {
@@ -137,44 +254,49 @@ pub async fn top_up_cycles(icp_amount: u64, canister: Principal) -> CanisterResu
         }),
     };
 
-    match transfer(MAINNET_LEDGER_CANISTER_ID, &args).await {
-        Ok(result) => match result {
-            Ok(block_index) => Ok(block_index),
-            Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
-                .add_method_name("top_up_cycles")
-                .add_source("toolkit_utils")),
-        },
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+    match transfer(network.ledger_canister_id, &args).await {
+        Ok(Ok(block_index)) => Ok(block_index),
+        Ok(Err(err)) => Err(ApiError::from(err)
+            .add_method_name("top_up_cycles")
+            .add_source("toolkit_utils")),
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("top_up_cycles")
             .add_source("toolkit_utils")),
     }
 }
 
 pub async fn topup_self(icp_amount: u64, canister: Principal) -> CanisterResult<Nat> {
-    let amount = Tokens::from_e8s(icp_amount - ICP_TRANSACTION_FEE);
+    topup_self_on(&network_config(), icp_amount, canister).await
+}
+
+pub async fn topup_self_on(
+    network: &NetworkConfig,
+    icp_amount: u64,
+    canister: Principal,
+) -> CanisterResult<Nat> {
+    let plan = plan_icp_transfer_on(network, icp_amount, FeePayer::Recipient, 1).await?;
+    let amount = Tokens::from_e8s(plan.net_e8s);
 
     let args = TransferArgs {
         memo: ic_ledger_types::Memo(MEMO_TOP_UP_CANISTER),
         amount,
-        fee: Tokens::from_e8s(ICP_TRANSACTION_FEE),
+        fee: Tokens::from_e8s(plan.fee_e8s),
         from_subaccount: None,
         to: AccountIdentifier::new(
-            &MAINNET_CYCLES_MINTING_CANISTER_ID,
+            &network.cycles_minting_canister_id,
             &Subaccount::from(canister),
         ),
         created_at_time: None,
     };
 
-    let transfer = transfer(MAINNET_LEDGER_CANISTER_ID, &args).await;
+    let transfer = transfer(network.ledger_canister_id, &args).await;
 
     match transfer {
-        Ok(result) => match result {
-            Ok(block_index) => self::notify_top_up_cycles(block_index).await,
-            Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
-                .add_method_name("topup_self")
-                .add_source("toolkit_utils")),
-        },
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Ok(Ok(block_index)) => notify_top_up_cycles_on(network, block_index).await,
+        Ok(Err(err)) => Err(ApiError::from(err)
+            .add_method_name("topup_self")
+            .add_source("toolkit_utils")),
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("topup_self")
             .add_source("toolkit_utils")),
     }
@@ -185,30 +307,38 @@ pub async fn topup_self_by_subaccount(
     canister: Principal,
     principal: Principal,
 ) -> CanisterResult<Nat> {
-    let amount = Tokens::from_e8s(icp_amount - ICP_TRANSACTION_FEE);
+    topup_self_by_subaccount_on(&network_config(), icp_amount, canister, principal).await
+}
+
+pub async fn topup_self_by_subaccount_on(
+    network: &NetworkConfig,
+    icp_amount: u64,
+    canister: Principal,
+    principal: Principal,
+) -> CanisterResult<Nat> {
+    let plan = plan_icp_transfer_on(network, icp_amount, FeePayer::Recipient, 1).await?;
+    let amount = Tokens::from_e8s(plan.net_e8s);
 
     let args = TransferArgs {
         memo: ic_ledger_types::Memo(MEMO_TOP_UP_CANISTER),
         amount,
-        fee: Tokens::from_e8s(ICP_TRANSACTION_FEE),
+        fee: Tokens::from_e8s(plan.fee_e8s),
         from_subaccount: Some(Subaccount::from(principal)),
         to: AccountIdentifier::new(
-            &MAINNET_CYCLES_MINTING_CANISTER_ID,
+            &network.cycles_minting_canister_id,
             &Subaccount::from(canister),
         ),
         created_at_time: None,
     };
 
-    let transfer = transfer(MAINNET_LEDGER_CANISTER_ID, &args).await;
+    let transfer = transfer(network.ledger_canister_id, &args).await;
 
     match transfer {
-        Ok(result) => match result {
-            Ok(block_index) => self::notify_top_up_cycles(block_index).await,
-            Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
-                .add_method_name("topup_self_by_subaccount")
-                .add_source("toolkit_utils")),
-        },
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Ok(Ok(block_index)) => notify_top_up_cycles_on(network, block_index).await,
+        Ok(Err(err)) => Err(ApiError::from(err)
+            .add_method_name("topup_self_by_subaccount")
+            .add_source("toolkit_utils")),
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("topup_self_by_subaccount")
             .add_source("toolkit_utils")),
     }
@@ -219,6 +349,17 @@ pub async fn send_to_canister_after_approve(
     canister: Principal,
     user_principal: Principal,
 ) -> CanisterResult<u64> {
+    send_to_canister_after_approve_on(&network_config(), icp_amount, canister, user_principal).await
+}
+
+pub async fn send_to_canister_after_approve_on(
+    network: &NetworkConfig,
+    icp_amount: u64,
+    canister: Principal,
+    user_principal: Principal,
+) -> CanisterResult<u64> {
+    let plan = plan_icp_transfer_on(network, icp_amount, FeePayer::Recipient, 1).await?;
+
     let args = TransferFromArgs {
         spender_subaccount: Some(Subaccount::from(user_principal).0),
         from: Account {
@@ -226,7 +367,7 @@ pub async fn send_to_canister_after_approve(
             subaccount: None,
         },
         memo: None,
-        amount: Nat::from(icp_amount - ICP_TRANSACTION_FEE),
+        amount: Nat::from(plan.net_e8s),
         fee: None,
         to: Account {
             owner: canister,
@@ -234,32 +375,39 @@ pub async fn send_to_canister_after_approve(
         },
         created_at_time: None,
     };
-    let result = Call::unbounded_wait(MAINNET_LEDGER_CANISTER_ID, "icrc2_transfer_from")
+    let result = Call::unbounded_wait(network.ledger_canister_id, "icrc2_transfer_from")
         .with_arg(&args)
         .await
         .map_err(|err| {
-            ApiError::external_service_error(&format!("{:?}", err))
+            ApiError::from(err)
                 .add_method_name("send_to_canister_after_approve")
                 .add_source("toolkit_utils")
         })?
         .candid::<Result<Nat, TransferFromError>>();
 
     match result {
-        Ok(Ok(response)) => Ok(nat_to_u64(&response)),
-        Ok(Err(err)) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Ok(Ok(response)) => try_nat_to_u64(&response),
+        Ok(Err(err)) => Err(ApiError::from(err)
             .add_method_name("send_to_canister_after_approve")
             .add_source("toolkit_utils")),
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("send_to_canister_after_approve")
             .add_source("toolkit_utils")),
     }
 }
 
 pub async fn notify_top_up_cycles(block_index: u64) -> CanisterResult<Nat> {
+    notify_top_up_cycles_on(&network_config(), block_index).await
+}
+
+pub async fn notify_top_up_cycles_on(
+    network: &NetworkConfig,
+    block_index: u64,
+) -> CanisterResult<Nat> {
     let method_name = "notify_top_up_cycles";
     let source = "toolkit_utils";
 
-    match CyclesMintingService(MAINNET_CYCLES_MINTING_CANISTER_ID)
+    match CyclesMintingService(network.cycles_minting_canister_id)
         .notify_top_up(NotifyTopUpArg {
             block_index,
             canister_id: canister_self(),
@@ -268,49 +416,28 @@ pub async fn notify_top_up_cycles(block_index: u64) -> CanisterResult<Nat> {
     {
         Ok((result,)) => match result {
             NotifyTopUpResult::Ok(cycles) => Ok(cycles),
-            NotifyTopUpResult::Err(err) => match err {
-                NotifyError::Refunded {
-                    block_index,
-                    reason,
-                } => Err(ApiError::external_service_error(&format!(
-                    "Refunded: block_index: {:?}, reason: {:?}",
-                    block_index, reason
-                ))
-                .add_method_name(method_name)
-                .add_source(source)),
-                NotifyError::InvalidTransaction(value) => Err(ApiError::external_service_error(
-                    &format!("InvalidTransaction: {:?}", value),
-                )
-                .add_method_name(method_name)
-                .add_source(source)),
-                NotifyError::Other {
-                    error_message,
-                    error_code,
-                } => Err(ApiError::external_service_error(&format!(
-                    "Other: error_message: {}, error_code: {:?}",
-                    error_message, error_code
-                ))
+            NotifyTopUpResult::Err(err) => Err(ApiError::from(err)
                 .add_method_name(method_name)
                 .add_source(source)),
-                NotifyError::Processing => Err(ApiError::external_service_error("Processing")),
-                NotifyError::TransactionTooOld(value) => Err(ApiError::external_service_error(
-                    &format!("TransactionTooOld: {:?}", value),
-                )
-                .add_method_name(method_name)
-                .add_source(source)),
-            },
         },
-        Err((_, err)) => Err(ApiError::external_service_error(&err)
+        Err(err) => Err(ApiError::from(err)
             .add_method_name(method_name)
             .add_source(source)),
     }
 }
 
 pub async fn notify_create(block_index: u64) -> CanisterResult<Principal> {
+    notify_create_on(&network_config(), block_index).await
+}
+
+pub async fn notify_create_on(
+    network: &NetworkConfig,
+    block_index: u64,
+) -> CanisterResult<Principal> {
     let method_name = "notify_create";
     let source = "toolkit_utils";
 
-    match CyclesMintingService(MAINNET_CYCLES_MINTING_CANISTER_ID)
+    match CyclesMintingService(network.cycles_minting_canister_id)
         .notify_create_canister(NotifyCreateCanisterArg {
             block_index,
             controller: canister_self(),
@@ -322,54 +449,31 @@ pub async fn notify_create(block_index: u64) -> CanisterResult<Principal> {
     {
         Ok((result,)) => match result {
             NotifyCreateCanisterResult::Ok(principal) => Ok(principal),
-            NotifyCreateCanisterResult::Err(err) => match err {
-                NotifyError::Refunded {
-                    block_index,
-                    reason,
-                } => Err(ApiError::external_service_error(&format!(
-                    "Refunded: block_index: {:?}, reason: {:?}",
-                    block_index, reason
-                ))
-                .add_method_name(method_name)
-                .add_source(source)),
-                NotifyError::InvalidTransaction(value) => Err(ApiError::external_service_error(
-                    &format!("InvalidTransaction: {:?}", value),
-                )
+            NotifyCreateCanisterResult::Err(err) => Err(ApiError::from(err)
                 .add_method_name(method_name)
                 .add_source(source)),
-                NotifyError::Other {
-                    error_message,
-                    error_code,
-                } => Err(ApiError::external_service_error(&format!(
-                    "Other: error_message: {}, error_code: {:?}",
-                    error_message, error_code
-                ))
-                .add_method_name(method_name)
-                .add_source(source)),
-                NotifyError::Processing => Err(ApiError::external_service_error("Processing")
-                    .add_method_name(method_name)
-                    .add_source(source)),
-                NotifyError::TransactionTooOld(value) => Err(ApiError::external_service_error(
-                    &format!("TransactionTooOld: {:?}", value),
-                )
-                .add_method_name(method_name)
-                .add_source(source)),
-            },
         },
-        Err((_, err)) => Err(ApiError::external_service_error(&err)
+        Err(err) => Err(ApiError::from(err)
             .add_method_name(method_name)
             .add_source(source)),
     }
 }
 
 pub async fn get_icp_balance(principal: Principal) -> CanisterResult<Tokens> {
+    get_icp_balance_on(&network_config(), principal).await
+}
+
+pub async fn get_icp_balance_on(
+    network: &NetworkConfig,
+    principal: Principal,
+) -> CanisterResult<Tokens> {
     let args = AccountBalanceArgs {
         account: principal_to_account_identifier(principal),
     };
 
-    match account_balance(MAINNET_LEDGER_CANISTER_ID, &args).await {
+    match account_balance(network.ledger_canister_id, &args).await {
         Ok(tokens) => Ok(tokens),
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("get_icp_balance")
             .add_source("toolkit_utils")),
     }
@@ -377,6 +481,13 @@ pub async fn get_icp_balance(principal: Principal) -> CanisterResult<Tokens> {
 
 pub async fn get_icp_allowance_by_canister_subaccount(
     principal: Principal,
+) -> CanisterResult<Allowance> {
+    get_icp_allowance_by_canister_subaccount_on(&network_config(), principal).await
+}
+
+pub async fn get_icp_allowance_by_canister_subaccount_on(
+    network: &NetworkConfig,
+    principal: Principal,
 ) -> CanisterResult<Allowance> {
     let args = AllowanceArgs {
         account: Account {
@@ -389,11 +500,11 @@ pub async fn get_icp_allowance_by_canister_subaccount(
         },
     };
 
-    let allowance = Call::unbounded_wait(MAINNET_LEDGER_CANISTER_ID, "icrc2_allowance")
+    let allowance = Call::unbounded_wait(network.ledger_canister_id, "icrc2_allowance")
         .with_arg(&args)
         .await
         .map_err(|err| {
-            ApiError::external_service_error(&format!("{:?}", err))
+            ApiError::from(err)
                 .add_method_name("get_icp_allowance_by_canister_subaccount")
                 .add_source("toolkit_utils")
         })?
@@ -401,7 +512,7 @@ pub async fn get_icp_allowance_by_canister_subaccount(
 
     match allowance {
         Ok(response) => Ok(response),
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("get_icp_allowance_by_canister_subaccount")
             .add_source("toolkit_utils")),
     }
@@ -411,6 +522,16 @@ pub async fn set_icp_approve_by_canister_subaccount(
     principal: Principal,
     amount: Nat,
     expires_at: Option<u64>,
+) -> CanisterResult<Approve> {
+    set_icp_approve_by_canister_subaccount_on(&network_config(), principal, amount, expires_at)
+        .await
+}
+
+pub async fn set_icp_approve_by_canister_subaccount_on(
+    network: &NetworkConfig,
+    principal: Principal,
+    amount: Nat,
+    expires_at: Option<u64>,
 ) -> CanisterResult<Approve> {
     let args = ApproveArgs {
         from_subaccount: None,
@@ -426,11 +547,11 @@ pub async fn set_icp_approve_by_canister_subaccount(
         created_at_time: None,
     };
 
-    let approve = Call::unbounded_wait(MAINNET_LEDGER_CANISTER_ID, "icrc2_approve")
+    let approve = Call::unbounded_wait(network.ledger_canister_id, "icrc2_approve")
         .with_arg(&args)
         .await
         .map_err(|err| {
-            ApiError::external_service_error(&format!("{:?}", err))
+            ApiError::from(err)
                 .add_method_name("set_icp_approve_by_canister_subaccount")
                 .add_source("toolkit_utils")
         })?
@@ -438,7 +559,7 @@ pub async fn set_icp_approve_by_canister_subaccount(
 
     match approve {
         Ok(response) => Ok(response),
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("set_icp_approve_by_canister_subaccount")
             .add_source("toolkit_utils")),
     }
@@ -446,14 +567,21 @@ pub async fn set_icp_approve_by_canister_subaccount(
 
 pub async fn get_icp_balance_by_canister_subaccount(
     subaccount: Principal,
+) -> CanisterResult<Tokens> {
+    get_icp_balance_by_canister_subaccount_on(&network_config(), subaccount).await
+}
+
+pub async fn get_icp_balance_by_canister_subaccount_on(
+    network: &NetworkConfig,
+    subaccount: Principal,
 ) -> CanisterResult<Tokens> {
     let args = AccountBalanceArgs {
         account: AccountIdentifier::new(&canister_self(), &Subaccount::from(subaccount)),
     };
 
-    match account_balance(MAINNET_LEDGER_CANISTER_ID, &args).await {
+    match account_balance(network.ledger_canister_id, &args).await {
         Ok(tokens) => Ok(tokens),
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("get_icp_balance_by_canister_subaccount")
             .add_source("toolkit_utils")),
     }
@@ -461,14 +589,21 @@ pub async fn get_icp_balance_by_canister_subaccount(
 
 pub async fn get_icp_balance_by_account_identifier(
     account_identifier: AccountIdentifier,
+) -> CanisterResult<Tokens> {
+    get_icp_balance_by_account_identifier_on(&network_config(), account_identifier).await
+}
+
+pub async fn get_icp_balance_by_account_identifier_on(
+    network: &NetworkConfig,
+    account_identifier: AccountIdentifier,
 ) -> CanisterResult<Tokens> {
     let args = AccountBalanceArgs {
         account: account_identifier,
     };
 
-    match account_balance(MAINNET_LEDGER_CANISTER_ID, &args).await {
+    match account_balance(network.ledger_canister_id, &args).await {
         Ok(tokens) => Ok(tokens),
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("get_icp_balance_by_account_identifier")
             .add_source("toolkit_utils")),
     }
@@ -479,7 +614,16 @@ pub async fn validate_transaction(
     to_principal: Principal,
     block_index: BlockIndex,
 ) -> Option<Tokens> {
-    let block = get_block(block_index).await?;
+    validate_transaction_on(&network_config(), from_principal, to_principal, block_index).await
+}
+
+pub async fn validate_transaction_on(
+    network: &NetworkConfig,
+    from_principal: Principal,
+    to_principal: Principal,
+    block_index: BlockIndex,
+) -> Option<Tokens> {
+    let block = get_block_on(network, block_index).await.ok()?;
 
     match block.transaction.operation? {
         Operation::Transfer {
@@ -500,30 +644,141 @@ pub async fn validate_transaction(
     }
 }
 
-async fn get_block(block_index: BlockIndex) -> Option<Block> {
+/// Verify an incoming ICP payment and mark its block as consumed, so the same payment can't be claimed twice.
+///
+/// Requires a registered consumed payments storage, see `register_consumed_payments_storage`.
+pub async fn verify_payment(
+    block_index: BlockIndex,
+    expected_to: AccountIdentifier,
+    expected_amount_e8s: u64,
+    expected_memo: u64,
+) -> CanisterResult<ConsumedPayment> {
+    verify_payment_on(
+        &network_config(),
+        block_index,
+        expected_to,
+        expected_amount_e8s,
+        expected_memo,
+    )
+    .await
+}
+
+pub async fn verify_payment_on(
+    network: &NetworkConfig,
+    block_index: BlockIndex,
+    expected_to: AccountIdentifier,
+    expected_amount_e8s: u64,
+    expected_memo: u64,
+) -> CanisterResult<ConsumedPayment> {
+    let invalid = |message: &str| {
+        ApiError::bad_request(message)
+            .add_method_name("verify_payment")
+            .add_info(block_index)
+            .add_source("toolkit_utils")
+    };
+
+    if is_payment_consumed(block_index)? {
+        return Err(ApiError::duplicate("Payment already consumed")
+            .add_method_name("verify_payment")
+            .add_info(block_index)
+            .add_source("toolkit_utils"));
+    }
+
+    let block = get_block_on(network, block_index).await?;
+
+    let (from, to, amount) = match block.transaction.operation {
+        Some(Operation::Transfer {
+            from, to, amount, ..
+        })
+        | Some(Operation::TransferFrom {
+            from, to, amount, ..
+        }) => (from, to, amount),
+        _ => return Err(invalid("Block is not a transfer")),
+    };
+
+    if to != expected_to {
+        return Err(invalid("Payment was sent to a different account"));
+    }
+    if amount.e8s() != expected_amount_e8s {
+        return Err(invalid("Payment amount does not match"));
+    }
+    if !memo_matches(&block.transaction, expected_memo) {
+        return Err(invalid("Payment memo does not match"));
+    }
+
+    // checked again after the await, so concurrent calls can't both consume the block
+    consume_payment(ConsumedPayment::new(
+        block_index,
+        from,
+        to,
+        amount.e8s(),
+        expected_memo,
+        block.timestamp.timestamp_nanos,
+    ))
+}
+
+// a transfer through ICRC-1 `transfer` carries its memo as bytes and leaves the legacy memo at 0
+fn memo_matches(transaction: &Transaction, expected_memo: u64) -> bool {
+    match &transaction.icrc1_memo {
+        Some(memo) => memo.as_slice() == Memo::from(expected_memo).0.as_slice(),
+        None => transaction.memo.0 == expected_memo,
+    }
+}
+
+async fn get_block_on(network: &NetworkConfig, block_index: BlockIndex) -> CanisterResult<Block> {
     let args = GetBlocksArgs {
         start: block_index,
         length: 1,
     };
 
-    if let Ok(blocks_result) = query_blocks(MAINNET_LEDGER_CANISTER_ID, &args).await {
-        if !blocks_result.blocks.is_empty() {
-            return blocks_result.blocks.into_iter().next();
-        }
+    let not_found = || {
+        ApiError::not_found("Block not found")
+            .add_method_name("get_block")
+            .add_info(block_index)
+            .add_source("toolkit_utils")
+    };
 
-        if let Some(func) = blocks_result.archived_blocks.into_iter().find_map(|b| {
-            (b.start <= block_index && (block_index - b.start) < b.length).then_some(b.callback)
-        }) {
-            if let Ok(range) = query_archived_blocks(&func, &args).await {
-                match range {
-                    Ok(_range) => return _range.blocks.into_iter().next(),
-                    Err(_) => return None,
-                }
-            }
-        }
+    let blocks_result = query_blocks(network.ledger_canister_id, &args)
+        .await
+        .map_err(|err| {
+            ApiError::from(err)
+                .add_method_name("get_block")
+                .add_source("toolkit_utils")
+        })?;
+
+    if !blocks_result.blocks.is_empty() {
+        return blocks_result
+            .blocks
+            .into_iter()
+            .next()
+            .ok_or_else(not_found);
     }
 
-    None
+    let func = blocks_result
+        .archived_blocks
+        .into_iter()
+        .find_map(|b| {
+            (b.start <= block_index && (block_index - b.start) < b.length).then_some(b.callback)
+        })
+        .ok_or_else(not_found)?;
+
+    query_archived_blocks(&func, &args)
+        .await
+        .map_err(|err| {
+            ApiError::from(err)
+                .add_method_name("get_block")
+                .add_source("toolkit_utils")
+        })?
+        .map_err(|err| {
+            ApiError::external_service_error(&format!("{:?}", err))
+                .add_method_name("get_block")
+                .add_info(block_index)
+                .add_source("toolkit_utils")
+        })?
+        .blocks
+        .into_iter()
+        .next()
+        .ok_or_else(not_found)
 }
 
 ////////////////////////////////////////////////////////////
@@ -535,15 +790,49 @@ pub async fn top_up_canister_cycles(
     icp_amount: u64,
     canister_id: Principal,
 ) -> CanisterResult<Nat> {
-    send_icp_to_canister_after_approve(icp_amount, from).await?;
-    let block_index = transfer_to_cmc(icp_amount, canister_id, from).await?;
-    notify_top_up_cycles_external_canister(block_index, canister_id).await
+    top_up_canister_cycles_on(&network_config(), from, icp_amount, canister_id).await
+}
+
+pub async fn top_up_canister_cycles_on(
+    network: &NetworkConfig,
+    from: Principal,
+    icp_amount: u64,
+    canister_id: Principal,
+) -> CanisterResult<Nat> {
+    send_icp_to_canister_after_approve_on(network, icp_amount, from)
+        .await
+        .with_context(
+            "Failed to collect the approved ICP",
+            "top_up_canister_cycles",
+        )?;
+    let block_index = transfer_to_cmc_on(network, icp_amount, canister_id, from)
+        .await
+        .with_context(
+            "Failed to transfer ICP to the CMC",
+            "top_up_canister_cycles",
+        )?;
+    notify_top_up_cycles_external_canister_on(network, block_index, canister_id)
+        .await
+        .with_context(
+            format!("Failed to notify the CMC about block {}", block_index),
+            "top_up_canister_cycles",
+        )
 }
 
 pub async fn send_icp_to_canister_after_approve(
     icp_amount: u64,
     user_principal: Principal,
 ) -> CanisterResult<u64> {
+    send_icp_to_canister_after_approve_on(&network_config(), icp_amount, user_principal).await
+}
+
+pub async fn send_icp_to_canister_after_approve_on(
+    network: &NetworkConfig,
+    icp_amount: u64,
+    user_principal: Principal,
+) -> CanisterResult<u64> {
+    let plan = plan_icp_transfer_on(network, icp_amount, FeePayer::Recipient, 1).await?;
+
     let args = TransferFromArgs {
         spender_subaccount: Some(Subaccount::from(user_principal).0),
         from: Account {
@@ -551,7 +840,7 @@ pub async fn send_icp_to_canister_after_approve(
             subaccount: None,
         },
         memo: None,
-        amount: Nat::from(icp_amount - ICP_TRANSACTION_FEE),
+        amount: Nat::from(plan.net_e8s),
         fee: None,
         to: Account {
             owner: canister_self(),
@@ -560,22 +849,22 @@ pub async fn send_icp_to_canister_after_approve(
         created_at_time: None,
     };
 
-    let result = Call::unbounded_wait(MAINNET_LEDGER_CANISTER_ID, "icrc2_transfer_from")
+    let result = Call::unbounded_wait(network.ledger_canister_id, "icrc2_transfer_from")
         .with_arg(&args)
         .await
         .map_err(|err| {
-            ApiError::external_service_error(&format!("{:?}", err))
+            ApiError::from(err)
                 .add_method_name("send_to_canister_after_approve_1")
                 .add_source("toolkit_utils")
         })?
         .candid::<Result<Nat, TransferFromError>>();
 
     match result {
-        Ok(Ok(block_index)) => Ok(nat_to_u64(&block_index)),
-        Ok(Err(err)) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Ok(Ok(block_index)) => try_nat_to_u64(&block_index),
+        Ok(Err(err)) => Err(ApiError::from(err)
             .add_method_name("send_to_canister_after_approve_2")
             .add_source("toolkit_utils")),
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("send_to_canister_after_approve_3")
             .add_source("toolkit_utils")),
     }
@@ -584,11 +873,19 @@ pub async fn send_icp_to_canister_after_approve(
 pub async fn notify_top_up_cycles_external_canister(
     block_index: u64,
     canister_id: Principal,
+) -> CanisterResult<Nat> {
+    notify_top_up_cycles_external_canister_on(&network_config(), block_index, canister_id).await
+}
+
+pub async fn notify_top_up_cycles_external_canister_on(
+    network: &NetworkConfig,
+    block_index: u64,
+    canister_id: Principal,
 ) -> CanisterResult<Nat> {
     let method_name = "notify_top_up_cycles";
     let source = "toolkit_utils";
 
-    match CyclesMintingService(MAINNET_CYCLES_MINTING_CANISTER_ID)
+    match CyclesMintingService(network.cycles_minting_canister_id)
         .notify_top_up(NotifyTopUpArg {
             block_index,
             canister_id,
@@ -597,39 +894,11 @@ pub async fn notify_top_up_cycles_external_canister(
     {
         Ok((result,)) => match result {
             NotifyTopUpResult::Ok(cycles) => Ok(cycles),
-            NotifyTopUpResult::Err(err) => match err {
-                NotifyError::Refunded {
-                    block_index,
-                    reason,
-                } => Err(ApiError::external_service_error(&format!(
-                    "Refunded: block_index: {:?}, reason: {:?}",
-                    block_index, reason
-                ))
-                .add_method_name(method_name)
-                .add_source(source)),
-                NotifyError::InvalidTransaction(value) => Err(ApiError::external_service_error(
-                    &format!("InvalidTransaction: {:?}", value),
-                )
-                .add_method_name(method_name)
-                .add_source(source)),
-                NotifyError::Other {
-                    error_message,
-                    error_code,
-                } => Err(ApiError::external_service_error(&format!(
-                    "Other: error_message: {}, error_code: {:?}",
-                    error_message, error_code
-                ))
+            NotifyTopUpResult::Err(err) => Err(ApiError::from(err)
                 .add_method_name(method_name)
                 .add_source(source)),
-                NotifyError::Processing => Err(ApiError::external_service_error("Processing")),
-                NotifyError::TransactionTooOld(value) => Err(ApiError::external_service_error(
-                    &format!("TransactionTooOld: {:?}", value),
-                )
-                .add_method_name(method_name)
-                .add_source(source)),
-            },
         },
-        Err((_, err)) => Err(ApiError::external_service_error(&err)
+        Err(err) => Err(ApiError::from(err)
             .add_method_name(method_name)
             .add_source(source)),
     }
@@ -640,14 +909,24 @@ pub async fn top_up_cycles_by_approve(
     from: Principal,
     canister: Principal,
 ) -> CanisterResult<u64> {
-    let amount = Tokens::from_e8s(icp_amount - ICP_TRANSACTION_FEE);
+    top_up_cycles_by_approve_on(&network_config(), icp_amount, from, canister).await
+}
+
+pub async fn top_up_cycles_by_approve_on(
+    network: &NetworkConfig,
+    icp_amount: u64,
+    from: Principal,
+    canister: Principal,
+) -> CanisterResult<u64> {
+    let plan = plan_icp_transfer_on(network, icp_amount, FeePayer::Recipient, 1).await?;
+    let amount = Tokens::from_e8s(plan.net_e8s);
 
     let args = TransferFromArgs {
         memo: Some(Memo::from(0x50555054)),
         amount: amount.e8s().into(),
         fee: None,
         to: Account {
-            owner: MAINNET_CYCLES_MINTING_CANISTER_ID,
+            owner: network.cycles_minting_canister_id,
             subaccount: Some(Subaccount::from(canister).0),
         },
         created_at_time: None,
@@ -658,19 +937,22 @@ pub async fn top_up_cycles_by_approve(
         },
     };
 
-    let result = Call::unbounded_wait(MAINNET_LEDGER_CANISTER_ID, "icrc2_transfer_from")
+    let result = Call::unbounded_wait(network.ledger_canister_id, "icrc2_transfer_from")
         .with_arg(&args)
         .await
         .map_err(|err| {
-            ApiError::external_service_error(&format!("{:?}", err))
+            ApiError::from(err)
                 .add_method_name("top_up_cycles_by_approve")
                 .add_source("toolkit_utils")
         })?
-        .candid::<Nat>();
+        .candid::<Result<Nat, TransferFromError>>();
 
     match result {
-        Ok(block_index) => Ok(nat_to_u64(&block_index)),
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+        Ok(Ok(block_index)) => try_nat_to_u64(&block_index),
+        Ok(Err(err)) => Err(ApiError::from(err)
+            .add_method_name("top_up_cycles_by_approve")
+            .add_source("toolkit_utils")),
+        Err(err) => Err(ApiError::from(err)
             .add_method_name("top_up_cycles_by_approve")
             .add_source("toolkit_utils")),
     }
@@ -684,15 +966,26 @@ pub async fn transfer_to_cmc(
     canister: Principal,
     from: Principal,
 ) -> CanisterResult<BlockIndex> {
-    let amount = Tokens::from_e8s(icp_amount - ICP_TRANSACTION_FEE - ICP_TRANSACTION_FEE);
+    transfer_to_cmc_on(&network_config(), icp_amount, canister, from).await
+}
+
+pub async fn transfer_to_cmc_on(
+    network: &NetworkConfig,
+    icp_amount: u64,
+    canister: Principal,
+    from: Principal,
+) -> CanisterResult<BlockIndex> {
+    // the ICP reached the subaccount through `send_icp_to_canister_after_approve`, which already paid one fee
+    let plan = plan_icp_transfer_on(network, icp_amount, FeePayer::Recipient, 2).await?;
+    let amount = Tokens::from_e8s(plan.amount_at(1));
 
     let args = TransferArgs {
         memo: ic_ledger_types::Memo(MEMO_TOP_UP_CANISTER),
         amount,
-        fee: Tokens::from_e8s(ICP_TRANSACTION_FEE),
+        fee: Tokens::from_e8s(plan.fee_e8s),
         from_subaccount: Some(Subaccount::from(from)),
         to: AccountIdentifier::new(
-            &MAINNET_CYCLES_MINTING_CANISTER_ID,
+            &network.cycles_minting_canister_id,
             &Subaccount::from(canister),
         ),
         created_at_time: Some(Timestamp {
@@ -700,15 +993,38 @@ pub async fn transfer_to_cmc(
         }),
     };
 
-    match transfer(MAINNET_LEDGER_CANISTER_ID, &args).await {
-        Ok(result) => match result {
-            Ok(block_index) => Ok(block_index),
-            Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
-                .add_method_name("top_up_cycles_via_subaccount")
-                .add_source("toolkit_utils")),
-        },
-        Err(err) => Err(ApiError::external_service_error(&format!("{:?}", err))
+    match transfer(network.ledger_canister_id, &args).await {
+        Ok(Ok(block_index)) => Ok(block_index),
+        Ok(Err(err)) => Err(ApiError::from(err)
             .add_method_name("top_up_cycles_via_subaccount")
             .add_source("toolkit_utils")),
+        Err(err) => Err(ApiError::from(err)
+            .add_method_name("top_up_cycles_via_subaccount")
+            .add_source("toolkit_utils")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(memo: u64, icrc1_memo: Option<u64>) -> Transaction {
+        Transaction {
+            memo: ic_ledger_types::Memo(memo),
+            operation: None,
+            created_at_time: Timestamp { timestamp_nanos: 0 },
+            icrc1_memo: icrc1_memo.map(|memo| Memo::from(memo).0),
+        }
+    }
+
+    #[test]
+    fn memo_matches_the_legacy_or_the_icrc1_memo() {
+        assert!(memo_matches(&transaction(42, None), 42));
+        assert!(!memo_matches(&transaction(0, None), 42));
+
+        assert!(memo_matches(&transaction(0, Some(42)), 42));
+        assert!(!memo_matches(&transaction(0, Some(41)), 42));
+        // the legacy memo of an ICRC-1 transfer is always 0
+        assert!(!memo_matches(&transaction(0, Some(41)), 0));
     }
 }
@@ -0,0 +1,202 @@
+use candid::{Nat, Principal};
+use ic_ledger_types::{transfer, AccountIdentifier, BlockIndex, Subaccount, TransferError};
+
+use crate::{
+    api_error::ApiError,
+    canister_call_error::CanisterCallError,
+    misc::generic::MEMO_TOP_UP_CANISTER,
+    network_config::NetworkConfig,
+    result::CanisterResult,
+    transfer_journal_entry::{TransferJournalEntry, TransferStatus},
+    StaticStorageRef,
+};
+
+use super::transactions::{icp_transfer_fee_on, notify_top_up_cycles_external_canister_on};
+
+pub static DEFAULT_MAX_TRANSFER_ATTEMPTS: u32 = 3;
+
+/// Stable journal of outgoing ICP transfers.
+///
+/// The intent, memo and `created_at_time` are stored before the ledger is called. A retry after a
+/// failed or unknown outcome sends the exact same transfer, so the ledger deduplicates it
+/// (`TxDuplicate { duplicate_of }`) instead of paying twice. Call `resume_pending` after an upgrade.
+#[derive(Clone, Copy)]
+pub struct TransferJournal {
+    storage: StaticStorageRef<u64, TransferJournalEntry>,
+    max_attempts: u32,
+}
+
+impl TransferJournal {
+    pub fn new(storage: StaticStorageRef<u64, TransferJournalEntry>) -> Self {
+        Self {
+            storage,
+            max_attempts: DEFAULT_MAX_TRANSFER_ATTEMPTS,
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Record the intent of a transfer without executing it
+    /// # Returns
+    /// * `Result<(u64, TransferJournalEntry), ApiError>` - The journal id and the entry
+    pub fn record(
+        &self,
+        entry: TransferJournalEntry,
+    ) -> CanisterResult<(u64, TransferJournalEntry)> {
+        self.storage.with(|data| {
+            let id = data
+                .borrow()
+                .last_key_value()
+                .map(|(k, _)| k + 1)
+                .unwrap_or(1);
+
+            data.borrow_mut().insert(id, entry.clone());
+            Ok((id, entry))
+        })
+    }
+
+    pub fn get(&self, id: u64) -> CanisterResult<TransferJournalEntry> {
+        self.storage
+            .with(|data| data.borrow().get(&id))
+            .ok_or_else(|| {
+                ApiError::not_found("Transfer not found in journal")
+                    .add_method_name("get")
+                    .add_info("transfer_journal")
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    pub fn get_pending(&self) -> Vec<(u64, TransferJournalEntry)> {
+        self.storage.with(|data| {
+            data.borrow()
+                .iter()
+                .filter(|(_, entry)| entry.is_pending())
+                .collect()
+        })
+    }
+
+    /// Record and execute an ICP transfer
+    /// # Returns
+    /// * `Result<(u64, BlockIndex), ApiError>` - The journal id and the block index of the transfer
+    pub async fn transfer_icp(
+        &self,
+        network: &NetworkConfig,
+        from_subaccount: Option<Subaccount>,
+        to: AccountIdentifier,
+        amount_e8s: u64,
+        memo: u64,
+    ) -> CanisterResult<(u64, BlockIndex)> {
+        let fee_e8s = icp_transfer_fee_on(network).await?;
+        let (id, _) = self.record(TransferJournalEntry::new(
+            network.ledger_canister_id,
+            from_subaccount,
+            to,
+            amount_e8s,
+            fee_e8s,
+            memo,
+        ))?;
+        let block_index = self.execute(id).await?;
+        Ok((id, block_index))
+    }
+
+    /// Journaled version of `top_up_cycles_and_notify`, the CMC deduplicates the notify by block index.
+    pub async fn top_up_cycles(
+        &self,
+        network: &NetworkConfig,
+        amount_e8s: u64,
+        canister_id: Principal,
+    ) -> CanisterResult<Nat> {
+        let to = AccountIdentifier::new(
+            &network.cycles_minting_canister_id,
+            &Subaccount::from(canister_id),
+        );
+        let (_, block_index) = self
+            .transfer_icp(network, None, to, amount_e8s, MEMO_TOP_UP_CANISTER)
+            .await?;
+        notify_top_up_cycles_external_canister_on(network, block_index, canister_id).await
+    }
+
+    /// Execute a journaled transfer, retrying transient failures with identical arguments.
+    ///
+    /// Completed entries return their block index without calling the ledger again.
+    pub async fn execute(&self, id: u64) -> CanisterResult<BlockIndex> {
+        let mut entry = self.get(id)?;
+
+        loop {
+            match &entry.status {
+                TransferStatus::Completed { block_index } => return Ok(*block_index),
+                TransferStatus::Failed { reason } => {
+                    return Err(ApiError::conflict(&format!("Transfer failed: {}", reason))
+                        .add_method_name("execute")
+                        .add_info(id)
+                        .add_source("toolkit_utils"))
+                }
+                TransferStatus::Pending => {}
+            }
+
+            entry.add_attempt();
+            self.save(id, &entry);
+
+            match transfer(entry.ledger_canister_id, &entry.to_transfer_args()).await {
+                Ok(Ok(block_index))
+                | Ok(Err(TransferError::TxDuplicate {
+                    duplicate_of: block_index,
+                })) => {
+                    entry.set_status(TransferStatus::Completed { block_index });
+                    self.save(id, &entry);
+                }
+                Ok(Err(err @ TransferError::TxCreatedInFuture)) => {
+                    return Err(ApiError::from(err)
+                        .add_method_name("execute")
+                        .add_info(id)
+                        .add_source("toolkit_utils"));
+                }
+                Ok(Err(err)) => {
+                    let reason = match err {
+                        TransferError::TxTooOld { .. } => {
+                            "Transaction is too old to be deduplicated, verify the ledger before paying again".to_string()
+                        }
+                        _ => format!("{:?}", err),
+                    };
+                    entry.set_status(TransferStatus::Failed { reason });
+                    self.save(id, &entry);
+                    return Err(ApiError::from(err)
+                        .add_method_name("execute")
+                        .add_info(id)
+                        .add_source("toolkit_utils"));
+                }
+                Err(err) => {
+                    let err = CanisterCallError::from(err);
+                    if !err.is_retryable_with_deduplication() || entry.attempts >= self.max_attempts
+                    {
+                        return Err(ApiError::from(err)
+                            .add_method_name("execute")
+                            .add_info(id)
+                            .add_source("toolkit_utils"));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Execute all pending transfers again, e.g. after an upgrade.
+    pub async fn resume_pending(&self) -> Vec<(u64, CanisterResult<BlockIndex>)> {
+        let mut results = vec![];
+        for (id, _) in self.get_pending() {
+            results.push((id, self.execute(id).await));
+        }
+        results
+    }
+
+    fn save(&self, id: u64, entry: &TransferJournalEntry) {
+        self.storage
+            .with(|data| data.borrow_mut().insert(id, entry.clone()));
+    }
+}
@@ -1,10 +1,11 @@
 use email_address::EmailAddress;
-use ic_cdk::api::time;
 use std::str::FromStr;
 
 use crate::{
     api_error::ApiError,
     date_range::DateRange,
+    error_code::{ErrorParam, ValidationCode},
+    misc::runtime::time,
     result::CanisterResult,
     validation::{ValidateField, ValidationResponse, ValidationType},
 };
@@ -56,16 +57,20 @@ impl Validator {
         field: &String,
     ) -> Result<(), ValidationResponse> {
         if str_len(value) < *min {
-            return Err(ValidationResponse {
-                field: field.to_string(),
-                message: format!("Minimum required length is {}", min),
-            });
+            return Err(ValidationResponse::new(
+                field,
+                ValidationCode::MinLength,
+                format!("Minimum required length is {}", min),
+                vec![ErrorParam::new("min", min)],
+            ));
         }
         if str_len(value) > *max {
-            return Err(ValidationResponse {
-                field: field.to_string(),
-                message: format!("Maximum length is {}", max),
-            });
+            return Err(ValidationResponse::new(
+                field,
+                ValidationCode::MaxLength,
+                format!("Maximum length is {}", max),
+                vec![ErrorParam::new("max", max)],
+            ));
         }
 
         Ok(())
@@ -78,16 +83,20 @@ impl Validator {
         field: &String,
     ) -> Result<(), ValidationResponse> {
         if value < min {
-            return Err(ValidationResponse {
-                field: field.to_string(),
-                message: format!("Minimum size length is {}", min),
-            });
+            return Err(ValidationResponse::new(
+                field,
+                ValidationCode::MinCount,
+                format!("Minimum size length is {}", min),
+                vec![ErrorParam::new("min", min)],
+            ));
         }
         if value > max {
-            return Err(ValidationResponse {
-                field: field.to_string(),
-                message: format!("Maximum size is {}", max),
-            });
+            return Err(ValidationResponse::new(
+                field,
+                ValidationCode::MaxCount,
+                format!("Maximum size is {}", max),
+                vec![ErrorParam::new("max", max)],
+            ));
         }
 
         Ok(())
@@ -98,25 +107,34 @@ impl Validator {
 
         match email {
             Ok(_email) => Ok(()),
-            Err(err) => Err(ValidationResponse {
-                field: field.to_string(),
-                message: err.to_string(),
-            }),
+            Err(err) => Err(ValidationResponse::new(
+                field,
+                ValidationCode::InvalidEmail,
+                err.to_string(),
+                vec![ErrorParam::new("reason", err)],
+            )),
         }
     }
 
     fn validate_date_range(value: &DateRange, field: &String) -> Result<(), ValidationResponse> {
         if value.start_date() > value.end_date() {
-            return Err(ValidationResponse {
-                field: field.to_string(),
-                message: "The start_date is after the end_date".to_string(),
-            });
+            return Err(ValidationResponse::new(
+                field,
+                ValidationCode::StartDateAfterEndDate,
+                "The start_date is after the end_date".to_string(),
+                vec![
+                    ErrorParam::new("start_date", value.start_date()),
+                    ErrorParam::new("end_date", value.end_date()),
+                ],
+            ));
         }
         if value.start_date() < time() {
-            return Err(ValidationResponse {
-                field: field.to_string(),
-                message: "The start_date can't be in the past".to_string(),
-            });
+            return Err(ValidationResponse::new(
+                field,
+                ValidationCode::StartDateInPast,
+                "The start_date can't be in the past".to_string(),
+                vec![ErrorParam::new("start_date", value.start_date())],
+            ));
         }
 
         Ok(())
@@ -24,6 +24,7 @@ pub static XDR_FEE_FOR_CORE_DEV: u64 = 500; // 0.05 XDR
 // cycles management canister related
 pub static MEMO_TOP_UP_CANISTER: u64 = 0x50555054;
 pub static MEMO_CREATE_CANISTER: u64 = 1095062083;
+pub static MEMO_MINT_CYCLES: u64 = 0x544e494d;
 
 // ICP related
 pub static ICP_TRANSACTION_FEE: u64 = 10_000;
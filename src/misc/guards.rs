@@ -1,7 +1,49 @@
 use candid::Principal;
-use ic_cdk::api::{self, msg_caller};
+use ic_stable_structures::Storable;
 
-use crate::{api_error::ApiError, result::CanisterResult};
+use crate::{
+    api_error::ApiError, cell::CellStorage, governance_config::GovernanceConfig,
+    management_config::ManagementConfig, result::CanisterResult,
+    trusted_canisters::TrustedCanisterStorage,
+};
+
+use super::runtime::{self, msg_caller};
+
+/// Exposes the canister relationships that are stored in a config cell,
+/// so the trusted-canister guards can be used with either config.
+pub trait CanisterRelations {
+    fn governance_canister_id(&self) -> Option<Principal>;
+    fn deployer_canister_id(&self) -> Option<Principal>;
+    fn management_canister_id(&self) -> Option<Principal>;
+}
+
+impl CanisterRelations for ManagementConfig {
+    fn governance_canister_id(&self) -> Option<Principal> {
+        Some(self.governance_canister_id)
+    }
+
+    fn deployer_canister_id(&self) -> Option<Principal> {
+        Some(self.deployer_canister_id)
+    }
+
+    fn management_canister_id(&self) -> Option<Principal> {
+        None
+    }
+}
+
+impl CanisterRelations for GovernanceConfig {
+    fn governance_canister_id(&self) -> Option<Principal> {
+        None
+    }
+
+    fn deployer_canister_id(&self) -> Option<Principal> {
+        Some(self.deployer_canister_id)
+    }
+
+    fn management_canister_id(&self) -> Option<Principal> {
+        Some(self.management_canister_id)
+    }
+}
 
 /// Validates if the caller is a controller of the canister.
 ///
@@ -16,7 +58,7 @@ use crate::{api_error::ApiError, result::CanisterResult};
 ///
 /// - Returns a string error message if the caller is not a controller.
 pub fn is_controller() -> CanisterResult<()> {
-    if !api::is_controller(&msg_caller()) {
+    if !runtime::is_controller(&msg_caller()) {
         return Err(ApiError::forbidden("Caller is not a controller")
             .add_method_name("is_controller")
             .add_source("toolkit_utils"));
@@ -74,3 +116,185 @@ pub fn is_admin() -> Result<(), String> {
             .to_string())
     }
 }
+
+/// Ensures that the caller is the governance canister stored in the config.
+///
+/// # Arguments
+///
+/// * `config` - The cell storage holding a config that knows the governance canister.
+///
+/// # Errors
+///
+/// - Returns an error if the config is not initialized, has no governance canister
+///   or the caller is not the governance canister.
+pub fn is_governance_canister<V, S>(config: &S) -> CanisterResult<()>
+where
+    V: CanisterRelations + Storable + Clone + 'static,
+    S: CellStorage<V>,
+{
+    is_related_canister(
+        config.get()?.governance_canister_id(),
+        "governance",
+        "is_governance_canister",
+    )
+}
+
+/// Ensures that the caller is the deployer canister stored in the config.
+///
+/// # Arguments
+///
+/// * `config` - The cell storage holding a config that knows the deployer canister.
+///
+/// # Errors
+///
+/// - Returns an error if the config is not initialized or the caller is not the deployer canister.
+pub fn is_deployer_canister<V, S>(config: &S) -> CanisterResult<()>
+where
+    V: CanisterRelations + Storable + Clone + 'static,
+    S: CellStorage<V>,
+{
+    is_related_canister(
+        config.get()?.deployer_canister_id(),
+        "deployer",
+        "is_deployer_canister",
+    )
+}
+
+/// Ensures that the caller is the management canister stored in the config.
+///
+/// # Arguments
+///
+/// * `config` - The cell storage holding a config that knows the management canister.
+///
+/// # Errors
+///
+/// - Returns an error if the config is not initialized, has no management canister
+///   or the caller is not the management canister.
+pub fn is_management_canister<V, S>(config: &S) -> CanisterResult<()>
+where
+    V: CanisterRelations + Storable + Clone + 'static,
+    S: CellStorage<V>,
+{
+    is_related_canister(
+        config.get()?.management_canister_id(),
+        "management",
+        "is_management_canister",
+    )
+}
+
+/// Ensures that the caller is on the trusted canister allowlist.
+///
+/// # Errors
+///
+/// - Returns an error if the caller is not a trusted canister.
+pub fn is_trusted_canister<S: TrustedCanisterStorage>() -> CanisterResult<()> {
+    if !S::is_trusted(&msg_caller()) {
+        return Err(ApiError::forbidden("Caller is not a trusted canister")
+            .add_method_name("is_trusted_canister")
+            .add_source("toolkit_utils"));
+    }
+    Ok(())
+}
+
+/// `Result<(), String>` variant of [`is_governance_canister`] for `#[update(guard = ...)]`.
+///
+/// Guards are referenced by a plain identifier, so forward to it from a canister function:
+/// `fn is_governance() -> Result<(), String> { is_governance_canister_guard(&config()) }`
+pub fn is_governance_canister_guard<V, S>(config: &S) -> Result<(), String>
+where
+    V: CanisterRelations + Storable + Clone + 'static,
+    S: CellStorage<V>,
+{
+    is_governance_canister(config).map_err(|err| err.to_string())
+}
+
+/// `Result<(), String>` variant of [`is_deployer_canister`] for `#[update(guard = ...)]`.
+pub fn is_deployer_canister_guard<V, S>(config: &S) -> Result<(), String>
+where
+    V: CanisterRelations + Storable + Clone + 'static,
+    S: CellStorage<V>,
+{
+    is_deployer_canister(config).map_err(|err| err.to_string())
+}
+
+/// `Result<(), String>` variant of [`is_management_canister`] for `#[update(guard = ...)]`.
+pub fn is_management_canister_guard<V, S>(config: &S) -> Result<(), String>
+where
+    V: CanisterRelations + Storable + Clone + 'static,
+    S: CellStorage<V>,
+{
+    is_management_canister(config).map_err(|err| err.to_string())
+}
+
+/// `Result<(), String>` variant of [`is_trusted_canister`] for `#[update(guard = ...)]`.
+pub fn is_trusted_canister_guard<S: TrustedCanisterStorage>() -> Result<(), String> {
+    is_trusted_canister::<S>().map_err(|err| err.to_string())
+}
+
+fn is_related_canister(
+    canister_id: Option<Principal>,
+    relation: &str,
+    method_name: &str,
+) -> CanisterResult<()> {
+    let canister_id = canister_id.ok_or_else(|| {
+        ApiError::unsupported(&format!("No {relation} canister configured"))
+            .add_method_name(method_name)
+            .add_source("toolkit_utils")
+    })?;
+
+    if msg_caller() != canister_id {
+        return Err(
+            ApiError::forbidden(&format!("Caller is not the {relation} canister"))
+                .add_method_name(method_name)
+                .add_source("toolkit_utils"),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::runtime::MockRuntime;
+
+    #[test]
+    fn is_controller_checks_the_caller() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+        let controller = Principal::from_slice(&[1]);
+        runtime.add_controller(controller);
+
+        runtime.set_caller(Principal::from_slice(&[2]));
+        assert!(is_controller().is_err());
+
+        runtime.set_caller(controller);
+        assert!(is_controller().is_ok());
+    }
+
+    #[test]
+    fn is_not_anonymous_rejects_the_anonymous_caller() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+
+        runtime.set_caller(Principal::anonymous());
+        assert!(is_not_anonymous().is_err());
+
+        runtime.set_caller(Principal::from_slice(&[1]));
+        assert!(is_not_anonymous().is_ok());
+    }
+
+    #[test]
+    fn is_admin_only_accepts_admins() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+
+        runtime.set_caller(Principal::from_slice(&[1]));
+        assert!(is_admin().is_err());
+
+        runtime.set_caller(
+            Principal::from_text("vafd2-aurwj-5igu3-htth5-olb42-6ficf-ttehy-2oyrp-u6nsy-qjlay-7ae")
+                .unwrap(),
+        );
+        assert!(is_admin().is_ok());
+    }
+}
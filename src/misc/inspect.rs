@@ -0,0 +1,304 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use candid::Principal;
+use ic_cdk::api::{accept_message, msg_arg_data, msg_method_name};
+
+use crate::{api_error::ApiError, result::CanisterResult};
+
+use super::{
+    guards::{is_admin, is_controller, is_not_anonymous},
+    runtime::{msg_caller, time},
+};
+
+/// Expired windows are evicted once the rate limit table grows to this many entries
+pub static RATE_LIMIT_PRUNE_SIZE: usize = 10_000;
+
+thread_local! {
+    // (bucket, caller) -> (window end in nanoseconds, calls in window)
+    static RATE_LIMITS: RefCell<HashMap<(String, Principal), (u64, u32)>> = RefCell::new(HashMap::new());
+}
+
+/// Who is allowed to call a method, checked with the existing guard functions.
+#[derive(Clone, Copy, Default)]
+pub enum CallerRequirement {
+    #[default]
+    Any,
+    NotAnonymous,
+    Controller,
+    Admin,
+    Guard(fn() -> Result<(), String>),
+}
+
+impl CallerRequirement {
+    fn check(&self) -> Result<(), String> {
+        use CallerRequirement::*;
+        match self {
+            Any => Ok(()),
+            NotAnonymous => is_not_anonymous(),
+            Controller => is_controller().map_err(|err| err.to_string()),
+            Admin => is_admin(),
+            Guard(guard) => guard(),
+        }
+    }
+}
+
+/// Allows `max_calls` calls per caller within `window_seconds` for every method sharing the bucket.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    pub bucket: String,
+    pub max_calls: u32,
+    pub window_seconds: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct MethodPolicy {
+    max_arg_size: Option<usize>,
+    caller: CallerRequirement,
+    rate_limit: Option<RateLimit>,
+}
+
+impl MethodPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_arg_size(mut self, bytes: usize) -> Self {
+        self.max_arg_size = Some(bytes);
+        self
+    }
+
+    pub fn caller(mut self, requirement: CallerRequirement) -> Self {
+        self.caller = requirement;
+        self
+    }
+
+    pub fn rate_limit<S: ToString>(
+        mut self,
+        bucket: S,
+        max_calls: u32,
+        window_seconds: u64,
+    ) -> Self {
+        self.rate_limit = Some(RateLimit {
+            bucket: bucket.to_string(),
+            max_calls,
+            window_seconds,
+        });
+        self
+    }
+}
+
+/// Declarative policy table used from `canister_inspect_message`.
+///
+/// Methods that are not in the table are rejected unless a default policy is set.
+#[derive(Clone, Default)]
+pub struct InspectPolicy {
+    methods: HashMap<String, MethodPolicy>,
+    default: Option<MethodPolicy>,
+}
+
+impl InspectPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn method<S: ToString>(mut self, method_name: S, policy: MethodPolicy) -> Self {
+        self.methods.insert(method_name.to_string(), policy);
+        self
+    }
+
+    pub fn default_policy(mut self, policy: MethodPolicy) -> Self {
+        self.default = Some(policy);
+        self
+    }
+
+    /// Checks a call against the policy table without changing any state.
+    /// # Arguments
+    /// * `method_name` - The name of the method that is called
+    /// * `arg_size` - The size of the candid encoded argument in bytes
+    /// # Returns
+    /// * `Result<(), ApiError>` - An error describing why the call is rejected
+    pub fn check(&self, method_name: &str, arg_size: usize) -> CanisterResult<()> {
+        let policy = self.get_policy(method_name, "check")?;
+
+        if let Some(max_arg_size) = policy.max_arg_size {
+            if arg_size > max_arg_size {
+                return Err(ApiError::payload_too_large(&format!(
+                    "Argument size {arg_size} exceeds the maximum of {max_arg_size} bytes"
+                ))
+                .add_method_name("check")
+                .add_info(method_name)
+                .add_source("toolkit_utils"));
+            }
+        }
+
+        policy.caller.check().map_err(|err| {
+            ApiError::forbidden(&err)
+                .add_method_name("check")
+                .add_info(method_name)
+                .add_source("toolkit_utils")
+        })?;
+
+        if let Some(rate_limit) = &policy.rate_limit {
+            if remaining_calls(rate_limit, msg_caller()) == 0 {
+                return Err(ApiError::forbidden("Rate limit exceeded")
+                    .add_method_name("check")
+                    .add_info(method_name)
+                    .add_info(&rate_limit.bucket)
+                    .add_source("toolkit_utils"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts a call against the rate limit bucket of the method.
+    ///
+    /// State changes made during `canister_inspect_message` are discarded,
+    /// so this has to be called from the update method itself.
+    pub fn consume(&self, method_name: &str) -> CanisterResult<()> {
+        let policy = self.get_policy(method_name, "consume")?;
+
+        if let Some(rate_limit) = &policy.rate_limit {
+            let caller = msg_caller();
+            if remaining_calls(rate_limit, caller) == 0 {
+                return Err(ApiError::forbidden("Rate limit exceeded")
+                    .add_method_name("consume")
+                    .add_info(method_name)
+                    .add_info(&rate_limit.bucket)
+                    .add_source("toolkit_utils"));
+            }
+
+            let now = time();
+            RATE_LIMITS.with(|limits| {
+                let mut limits = limits.borrow_mut();
+                // every caller adds an entry, so drop the windows that ended to keep the table bounded
+                if limits.len() >= RATE_LIMIT_PRUNE_SIZE {
+                    limits.retain(|_, (window_end, _)| *window_end > now);
+                }
+
+                let entry = limits
+                    .entry((rate_limit.bucket.clone(), caller))
+                    .or_insert((window_end(rate_limit, now), 0));
+                if entry.0 <= now {
+                    *entry = (window_end(rate_limit, now), 0);
+                }
+                entry.1 += 1;
+            });
+        }
+
+        Ok(())
+    }
+
+    fn get_policy(&self, method_name: &str, source_method: &str) -> CanisterResult<&MethodPolicy> {
+        self.methods
+            .get(method_name)
+            .or(self.default.as_ref())
+            .ok_or_else(|| {
+                ApiError::forbidden("Method is not allowed")
+                    .add_method_name(source_method)
+                    .add_info(method_name)
+                    .add_source("toolkit_utils")
+            })
+    }
+}
+
+/// Accepts or rejects the current message based on the policy table.
+///
+/// Call this from the `#[inspect_message]` function of the canister,
+/// the message is only accepted when the policy check passes.
+pub fn inspect_message(policy: &InspectPolicy) -> CanisterResult<()> {
+    let result = policy.check(&msg_method_name(), msg_arg_data().len());
+    if result.is_ok() {
+        accept_message();
+    }
+    result
+}
+
+fn remaining_calls(rate_limit: &RateLimit, caller: Principal) -> u32 {
+    let now = time();
+    RATE_LIMITS.with(
+        |limits| match limits.borrow().get(&(rate_limit.bucket.clone(), caller)) {
+            Some((window_end, calls)) if *window_end > now => {
+                rate_limit.max_calls.saturating_sub(*calls)
+            }
+            _ => rate_limit.max_calls,
+        },
+    )
+}
+
+fn window_end(rate_limit: &RateLimit, now: u64) -> u64 {
+    now.saturating_add(rate_limit.window_seconds.saturating_mul(1_000_000_000))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::runtime::MockRuntime;
+
+    fn policy() -> InspectPolicy {
+        InspectPolicy::new().method(
+            "create",
+            MethodPolicy::new()
+                .max_arg_size(100)
+                .caller(CallerRequirement::NotAnonymous)
+                .rate_limit("create", 2, 60),
+        )
+    }
+
+    #[test]
+    fn check_rejects_unknown_methods_large_args_and_anonymous_callers() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+        runtime.set_caller(Principal::from_slice(&[1]));
+
+        assert!(policy().check("create", 10).is_ok());
+        assert!(policy().check("delete", 10).is_err());
+        assert!(policy().check("create", 101).is_err());
+
+        runtime.set_caller(Principal::anonymous());
+        assert!(policy().check("create", 10).is_err());
+    }
+
+    #[test]
+    fn rate_limit_resets_after_the_window() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+        runtime.set_caller(Principal::from_slice(&[1]));
+        let policy = policy();
+
+        assert!(policy.consume("create").is_ok());
+        assert!(policy.consume("create").is_ok());
+        assert!(policy.consume("create").is_err());
+        assert!(policy.check("create", 10).is_err());
+
+        // other callers have their own limit
+        runtime.set_caller(Principal::from_slice(&[2]));
+        assert!(policy.check("create", 10).is_ok());
+
+        runtime.set_caller(Principal::from_slice(&[1]));
+        runtime.advance_time(60 * 1_000_000_000);
+        assert!(policy.check("create", 10).is_ok());
+        assert!(policy.consume("create").is_ok());
+    }
+
+    #[test]
+    fn expired_windows_are_evicted() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+        let policy = policy();
+
+        for index in 0..RATE_LIMIT_PRUNE_SIZE as u32 {
+            runtime.set_caller(Principal::from_slice(&index.to_be_bytes()));
+            assert!(policy.consume("create").is_ok());
+        }
+        assert_eq!(
+            RATE_LIMITS.with(|limits| limits.borrow().len()),
+            RATE_LIMIT_PRUNE_SIZE
+        );
+
+        runtime.advance_time(60 * 1_000_000_000);
+        runtime.set_caller(Principal::from_slice(&[1, 2, 3, 4, 5]));
+        assert!(policy.consume("create").is_ok());
+        assert_eq!(RATE_LIMITS.with(|limits| limits.borrow().len()), 1);
+    }
+}
@@ -5,7 +5,7 @@ macro_rules! impl_storable_for {
         impl Storable for $type {
             const BOUND: Bound = Bound::Unbounded;
 
-            fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+            fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
                 use candid::Encode;
                 use std::borrow::Cow;
                 Cow::Owned(Encode!(&self).expect(concat!("Failed to encode ", stringify!($type))))
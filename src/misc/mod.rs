@@ -2,5 +2,8 @@ pub mod generic;
 pub mod guards;
 pub mod hash;
 pub mod image;
+pub mod inspect;
 pub mod macros;
+pub mod reentrancy;
+pub mod runtime;
 pub mod wasm;
@@ -0,0 +1,23 @@
+use std::{cell::Cell, thread::LocalKey};
+
+/// Holds a `thread_local` flag for as long as it lives, so a timer that fires while the previous run
+/// is still awaiting does not start a second run.
+///
+/// The flag is reset on drop, which also happens when a callback traps and the future is cleaned up.
+pub struct ReentrancyGuard(&'static LocalKey<Cell<bool>>);
+
+impl ReentrancyGuard {
+    /// `None` when the flag is already held
+    pub fn acquire(flag: &'static LocalKey<Cell<bool>>) -> Option<Self> {
+        if flag.with(|flag| flag.replace(true)) {
+            return None;
+        }
+        Some(Self(flag))
+    }
+}
+
+impl Drop for ReentrancyGuard {
+    fn drop(&mut self) {
+        self.0.with(|flag| flag.set(false));
+    }
+}
@@ -0,0 +1,180 @@
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+use candid::Principal;
+use ic_cdk::api;
+
+thread_local! {
+    static RUNTIME: RefCell<Rc<dyn Runtime>> = RefCell::new(Rc::new(IcRuntime));
+}
+
+/// Source of the time and caller context that the crate uses.
+///
+/// Canisters use `IcRuntime`, which is the default. Native tests install a `MockRuntime`
+/// because the system api traps outside of a canister.
+pub trait Runtime {
+    fn time(&self) -> u64;
+    fn msg_caller(&self) -> Principal;
+    fn canister_self(&self) -> Principal;
+    fn is_controller(&self, principal: &Principal) -> bool;
+}
+
+pub struct IcRuntime;
+
+impl Runtime for IcRuntime {
+    fn time(&self) -> u64 {
+        api::time()
+    }
+
+    fn msg_caller(&self) -> Principal {
+        api::msg_caller()
+    }
+
+    fn canister_self(&self) -> Principal {
+        api::canister_self()
+    }
+
+    fn is_controller(&self, principal: &Principal) -> bool {
+        api::is_controller(principal)
+    }
+}
+
+#[derive(Clone)]
+struct MockState {
+    time: u64,
+    msg_caller: Principal,
+    canister_self: Principal,
+    controllers: HashSet<Principal>,
+}
+
+/// Runtime for native tests where the test controls the time and caller.
+///
+/// Clones share their state, so keep a clone after `install` to change it:
+/// ```ignore
+/// let runtime = MockRuntime::new();
+/// runtime.install();
+/// runtime.set_caller(user);
+/// runtime.advance_time(DAY_IN_SECONDS * 1_000_000_000);
+/// ```
+#[derive(Clone)]
+pub struct MockRuntime {
+    state: Rc<RefCell<MockState>>,
+}
+
+impl Default for MockRuntime {
+    fn default() -> Self {
+        Self {
+            state: Rc::new(RefCell::new(MockState {
+                time: 0,
+                msg_caller: Principal::anonymous(),
+                canister_self: Principal::management_canister(),
+                controllers: HashSet::new(),
+            })),
+        }
+    }
+}
+
+impl MockRuntime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make this runtime the one used by the crate on the current thread.
+    pub fn install(&self) {
+        set_runtime(self.clone());
+    }
+
+    pub fn set_time(&self, nanos: u64) {
+        self.state.borrow_mut().time = nanos;
+    }
+
+    pub fn advance_time(&self, nanos: u64) {
+        let mut state = self.state.borrow_mut();
+        state.time = state.time.saturating_add(nanos);
+    }
+
+    pub fn set_caller(&self, principal: Principal) {
+        self.state.borrow_mut().msg_caller = principal;
+    }
+
+    pub fn set_canister_self(&self, principal: Principal) {
+        self.state.borrow_mut().canister_self = principal;
+    }
+
+    pub fn add_controller(&self, principal: Principal) {
+        self.state.borrow_mut().controllers.insert(principal);
+    }
+}
+
+impl Runtime for MockRuntime {
+    fn time(&self) -> u64 {
+        self.state.borrow().time
+    }
+
+    fn msg_caller(&self) -> Principal {
+        self.state.borrow().msg_caller
+    }
+
+    fn canister_self(&self) -> Principal {
+        self.state.borrow().canister_self
+    }
+
+    fn is_controller(&self, principal: &Principal) -> bool {
+        self.state.borrow().controllers.contains(principal)
+    }
+}
+
+pub fn set_runtime<R: Runtime + 'static>(runtime: R) {
+    RUNTIME.with(|current| *current.borrow_mut() = Rc::new(runtime));
+}
+
+/// Current time in nanoseconds, from the installed runtime.
+pub fn time() -> u64 {
+    RUNTIME.with(|runtime| runtime.borrow().time())
+}
+
+/// Caller of the current message, from the installed runtime.
+pub fn msg_caller() -> Principal {
+    RUNTIME.with(|runtime| runtime.borrow().msg_caller())
+}
+
+/// Principal of the canister itself, from the installed runtime.
+pub fn canister_self() -> Principal {
+    RUNTIME.with(|runtime| runtime.borrow().canister_self())
+}
+
+pub fn is_controller(principal: &Principal) -> bool {
+    RUNTIME.with(|runtime| runtime.borrow().is_controller(principal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_runtime_controls_time_and_caller() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+        let caller = Principal::from_slice(&[1]);
+
+        runtime.set_time(10);
+        runtime.advance_time(5);
+        runtime.set_caller(caller);
+        runtime.add_controller(caller);
+
+        assert_eq!(time(), 15);
+        assert_eq!(msg_caller(), caller);
+        assert!(is_controller(&caller));
+        assert!(!is_controller(&Principal::anonymous()));
+    }
+
+    #[test]
+    fn advance_time_saturates() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+
+        runtime.set_time(u64::MAX - 1);
+        runtime.advance_time(10);
+
+        assert_eq!(time(), u64::MAX);
+    }
+}
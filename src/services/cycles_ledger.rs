@@ -0,0 +1,157 @@
+use candid::{CandidType, Deserialize, Nat, Principal};
+use ic_cdk::call::Call;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    api_error::ApiError,
+    cycles_minting::{CanisterSettings, SubnetSelection},
+    result::CanisterResult,
+};
+
+pub type BlockIndex = Nat;
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct WithdrawArgs {
+    pub amount: Nat,
+    pub from_subaccount: Option<Vec<u8>>,
+    pub to: Principal,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CyclesLedgerRejectionCode {
+    NoError,
+    CanisterError,
+    SysTransient,
+    DestinationInvalid,
+    Unknown,
+    SysFatal,
+    CanisterReject,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum WithdrawError {
+    BadFee {
+        expected_fee: Nat,
+    },
+    InsufficientFunds {
+        balance: Nat,
+    },
+    TooOld,
+    CreatedInFuture {
+        ledger_time: u64,
+    },
+    TemporarilyUnavailable,
+    Duplicate {
+        duplicate_of: Nat,
+    },
+    FailedToWithdraw {
+        fee_block: Option<Nat>,
+        rejection_code: CyclesLedgerRejectionCode,
+        rejection_reason: String,
+    },
+    GenericError {
+        error_code: Nat,
+        message: String,
+    },
+    InvalidReceiver {
+        receiver: Principal,
+    },
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct CmcCreateCanisterArgs {
+    pub settings: Option<CanisterSettings>,
+    pub subnet_selection: Option<SubnetSelection>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct CreateCanisterArgs {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+    pub amount: Nat,
+    pub creation_args: Option<CmcCreateCanisterArgs>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct CreateCanisterSuccess {
+    pub block_id: Nat,
+    pub canister_id: Principal,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum CreateCanisterError {
+    InsufficientFunds {
+        balance: Nat,
+    },
+    TooOld,
+    CreatedInFuture {
+        ledger_time: u64,
+    },
+    TemporarilyUnavailable,
+    Duplicate {
+        duplicate_of: Nat,
+        canister_id: Option<Principal>,
+    },
+    FailedToCreate {
+        fee_block: Option<Nat>,
+        refund_block: Option<Nat>,
+        error: String,
+    },
+    GenericError {
+        error_code: Nat,
+        message: String,
+    },
+}
+
+/// Client for the cycles ledger specific endpoints, the ICRC-1 / ICRC-2 endpoints are available through `TokenLedger`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CyclesLedgerService(pub Principal);
+
+impl CyclesLedgerService {
+    pub async fn withdraw(&self, args: WithdrawArgs) -> CanisterResult<BlockIndex> {
+        self.call::<_, Result<BlockIndex, WithdrawError>>("withdraw", &args)
+            .await?
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name("withdraw")
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    pub async fn create_canister(
+        &self,
+        args: CreateCanisterArgs,
+    ) -> CanisterResult<CreateCanisterSuccess> {
+        self.call::<_, Result<CreateCanisterSuccess, CreateCanisterError>>("create_canister", &args)
+            .await?
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name("create_canister")
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    async fn call<A: CandidType, R: CandidType + DeserializeOwned>(
+        &self,
+        method: &str,
+        arg: &A,
+    ) -> CanisterResult<R> {
+        Call::unbounded_wait(self.0, method)
+            .with_arg(arg)
+            .await
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name(method)
+                    .add_info(self.0)
+                    .add_source("toolkit_utils")
+            })?
+            .candid::<R>()
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name(method)
+                    .add_info(self.0)
+                    .add_source("toolkit_utils")
+            })
+    }
+}
@@ -106,7 +106,7 @@ pub struct NotifyCreateCanisterArg {
     pub subnet_type: Option<String>,
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Deserialize, serde::Serialize, Clone, Debug)]
 pub enum NotifyError {
     Refunded {
         block_index: Option<BlockIndex>,
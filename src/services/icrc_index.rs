@@ -0,0 +1,177 @@
+use candid::{CandidType, Deserialize, Nat, Principal};
+use ic_cdk::call::Call;
+use icrc_ledger_types::{
+    icrc::generic_value::ICRC3Value,
+    icrc1::account::Account,
+    icrc3::{blocks::GetBlocksRequest, transactions::Transaction},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    account_transaction::{AccountTransaction, AccountTransactionsPage},
+    api_error::ApiError,
+    conversions::try_nat_to_u64,
+    result::CanisterResult,
+};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GetAccountTransactionsArgs {
+    pub account: Account,
+    /// The id of the last transaction seen by the client, `None` starts from the most recent transaction
+    pub start: Option<Nat>,
+    pub max_results: Nat,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransactionWithId {
+    pub id: Nat,
+    pub transaction: Transaction,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GetTransactions {
+    pub balance: Nat,
+    pub transactions: Vec<TransactionWithId>,
+    pub oldest_tx_id: Option<Nat>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GetTransactionsErr {
+    pub message: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum GetTransactionsResult {
+    Ok(GetTransactions),
+    Err(GetTransactionsErr),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GetIndexBlocksResponse {
+    pub chain_length: u64,
+    pub blocks: Vec<ICRC3Value>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct IndexStatus {
+    pub num_blocks_synced: Nat,
+}
+
+/// Client for an ICRC-1 index canister (index-ng), e.g. the `index_canister_id` of the `ManagementConfig`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IcrcIndex(pub Principal);
+
+impl IcrcIndex {
+    pub fn new(index_canister_id: Principal) -> Self {
+        Self(index_canister_id)
+    }
+
+    pub fn canister_id(&self) -> Principal {
+        self.0
+    }
+
+    pub async fn get_account_transactions(
+        &self,
+        args: GetAccountTransactionsArgs,
+    ) -> CanisterResult<GetTransactions> {
+        match self
+            .call::<_, GetTransactionsResult>("get_account_transactions", &args)
+            .await?
+        {
+            GetTransactionsResult::Ok(result) => Ok(result),
+            GetTransactionsResult::Err(err) => Err(ApiError::external_service_error(&err.message)
+                .add_method_name("get_account_transactions")
+                .add_info(self.0)
+                .add_source("toolkit_utils")),
+        }
+    }
+
+    pub async fn get_blocks(
+        &self,
+        args: GetBlocksRequest,
+    ) -> CanisterResult<GetIndexBlocksResponse> {
+        self.call("get_blocks", &args).await
+    }
+
+    pub async fn status(&self) -> CanisterResult<IndexStatus> {
+        self.call("status", &()).await
+    }
+
+    pub async fn ledger_id(&self) -> CanisterResult<Principal> {
+        self.call("ledger_id", &()).await
+    }
+
+    /// A page of the transaction history of `account`, newest first
+    /// # Arguments
+    /// * `account` - The account to fetch the history for
+    /// * `start` - The `next_start` of the previous page, `None` for the first page
+    /// * `max_results` - The maximum number of transactions in the page
+    pub async fn get_account_history(
+        &self,
+        account: Account,
+        start: Option<u64>,
+        max_results: u64,
+    ) -> CanisterResult<AccountTransactionsPage> {
+        let result = self
+            .get_account_transactions(GetAccountTransactionsArgs {
+                account,
+                start: start.map(Nat::from),
+                max_results: Nat::from(max_results),
+            })
+            .await?;
+
+        let oldest_tx_id = result
+            .oldest_tx_id
+            .as_ref()
+            .map(try_nat_to_u64)
+            .transpose()?;
+
+        let transactions = result
+            .transactions
+            .into_iter()
+            .map(|tx| {
+                Ok(AccountTransaction::from_transaction(
+                    try_nat_to_u64(&tx.id)?,
+                    &account,
+                    tx.transaction,
+                ))
+            })
+            .collect::<CanisterResult<Vec<_>>>()?;
+
+        // `start` is exclusive, so the next page starts at the last returned id
+        let next_start = match (transactions.last(), oldest_tx_id) {
+            (Some(last), Some(oldest)) if last.id > oldest => Some(last.id),
+            _ => None,
+        };
+
+        Ok(AccountTransactionsPage {
+            balance: result.balance,
+            transactions,
+            oldest_tx_id,
+            next_start,
+        })
+    }
+
+    async fn call<A: CandidType, R: CandidType + DeserializeOwned>(
+        &self,
+        method: &str,
+        arg: &A,
+    ) -> CanisterResult<R> {
+        Call::unbounded_wait(self.0, method)
+            .with_arg(arg)
+            .await
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name(method)
+                    .add_info(self.0)
+                    .add_source("toolkit_utils")
+            })?
+            .candid::<R>()
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name(method)
+                    .add_info(self.0)
+                    .add_source("toolkit_utils")
+            })
+    }
+}
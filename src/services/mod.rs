@@ -1 +1,4 @@
+pub mod cycles_ledger;
 pub mod cycles_minting;
+pub mod icrc_index;
+pub mod token_ledger;
@@ -0,0 +1,124 @@
+use candid::{CandidType, Nat, Principal};
+use ic_cdk::call::Call;
+use icrc_ledger_types::{
+    icrc::generic_metadata_value::MetadataValue,
+    icrc1::{
+        account::{Account, Subaccount},
+        transfer::{BlockIndex, Memo, TransferArg, TransferError},
+    },
+    icrc2::{
+        allowance::{Allowance, AllowanceArgs},
+        approve::{ApproveArgs, ApproveError},
+        transfer_from::{TransferFromArgs, TransferFromError},
+    },
+};
+use serde::de::DeserializeOwned;
+
+use crate::{api_error::ApiError, result::CanisterResult};
+
+/// Client for any ICRC-1 / ICRC-2 ledger, e.g. the ICP ledger, ckBTC, ckUSDC or a project token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenLedger(pub Principal);
+
+impl TokenLedger {
+    pub fn new(ledger_canister_id: Principal) -> Self {
+        Self(ledger_canister_id)
+    }
+
+    pub fn canister_id(&self) -> Principal {
+        self.0
+    }
+
+    pub async fn icrc1_balance_of(&self, account: Account) -> CanisterResult<Nat> {
+        self.call("icrc1_balance_of", &account).await
+    }
+
+    pub async fn icrc1_fee(&self) -> CanisterResult<Nat> {
+        self.call("icrc1_fee", &()).await
+    }
+
+    pub async fn icrc1_decimals(&self) -> CanisterResult<u8> {
+        self.call("icrc1_decimals", &()).await
+    }
+
+    pub async fn icrc1_metadata(&self) -> CanisterResult<Vec<(String, MetadataValue)>> {
+        self.call("icrc1_metadata", &()).await
+    }
+
+    pub async fn icrc1_transfer(&self, args: TransferArg) -> CanisterResult<BlockIndex> {
+        self.call::<_, Result<BlockIndex, TransferError>>("icrc1_transfer", &args)
+            .await?
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name("icrc1_transfer")
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    /// Transfer from a subaccount of this canister, the ledger fee is set by the ledger.
+    pub async fn transfer(
+        &self,
+        from_subaccount: Option<Subaccount>,
+        to: Account,
+        amount: Nat,
+        memo: Option<Memo>,
+        created_at_time: Option<u64>,
+    ) -> CanisterResult<BlockIndex> {
+        self.icrc1_transfer(TransferArg {
+            from_subaccount,
+            to,
+            fee: None,
+            created_at_time,
+            memo,
+            amount,
+        })
+        .await
+    }
+
+    pub async fn icrc2_approve(&self, args: ApproveArgs) -> CanisterResult<BlockIndex> {
+        self.call::<_, Result<BlockIndex, ApproveError>>("icrc2_approve", &args)
+            .await?
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name("icrc2_approve")
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    pub async fn icrc2_allowance(&self, args: AllowanceArgs) -> CanisterResult<Allowance> {
+        self.call("icrc2_allowance", &args).await
+    }
+
+    pub async fn icrc2_transfer_from(&self, args: TransferFromArgs) -> CanisterResult<BlockIndex> {
+        self.call::<_, Result<BlockIndex, TransferFromError>>("icrc2_transfer_from", &args)
+            .await?
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name("icrc2_transfer_from")
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    async fn call<A: CandidType, R: CandidType + DeserializeOwned>(
+        &self,
+        method: &str,
+        arg: &A,
+    ) -> CanisterResult<R> {
+        Call::unbounded_wait(self.0, method)
+            .with_arg(arg)
+            .await
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name(method)
+                    .add_info(self.0)
+                    .add_source("toolkit_utils")
+            })?
+            .candid::<R>()
+            .map_err(|err| {
+                ApiError::from(err)
+                    .add_method_name(method)
+                    .add_info(self.0)
+                    .add_source("toolkit_utils")
+            })
+    }
+}
@@ -1,3 +1,4 @@
 pub mod cell;
 pub mod list;
 pub mod storage;
+pub mod trusted_canisters;
@@ -0,0 +1,59 @@
+use candid::Principal;
+
+use crate::{
+    api_error::ApiError,
+    result::CanisterResult,
+    storage::{StorageInsertableByKey, StorageQueryable, StorageUpdateable},
+    trusted_canister::{TrustedCanister, TrustedCanisterResponse},
+};
+
+/// Runtime managed allowlist of canisters that are trusted to call inter-canister endpoints.
+///
+/// Implement this on the storage struct that holds the allowlist, the default methods
+/// take care of the bookkeeping and are used by `misc::guards::is_trusted_canister`.
+pub trait TrustedCanisterStorage:
+    StorageQueryable<Principal, TrustedCanister>
+    + StorageInsertableByKey<Principal, TrustedCanister>
+    + StorageUpdateable<Principal, TrustedCanister>
+{
+    /// Add a canister to the allowlist
+    /// # Arguments
+    /// * `canister_id` - The canister to trust
+    /// * `label` - Optional label to describe the canister
+    /// # Returns
+    /// * `Result<(Principal, TrustedCanister), ApiError>` - The added entry, or an error if it already exists
+    fn add_trusted_canister(
+        canister_id: Principal,
+        label: Option<String>,
+    ) -> CanisterResult<(Principal, TrustedCanister)> {
+        Self::insert_by_key(canister_id, TrustedCanister::new(label))
+    }
+
+    /// Remove a canister from the allowlist
+    /// # Arguments
+    /// * `canister_id` - The canister to remove
+    /// # Returns
+    /// * `Result<(), ApiError>` - An error if the canister was not on the allowlist
+    fn remove_trusted_canister(canister_id: Principal) -> CanisterResult<()> {
+        if !Self::remove(canister_id) {
+            return Err(ApiError::not_found("Canister is not trusted")
+                .add_method_name("remove_trusted_canister")
+                .add_info(Self::NAME)
+                .add_source("toolkit_utils"));
+        }
+        Ok(())
+    }
+
+    /// Check if a canister is on the allowlist
+    fn is_trusted(canister_id: &Principal) -> bool {
+        Self::storage().with(|data| data.borrow().contains_key(canister_id))
+    }
+
+    /// Get all trusted canisters
+    fn get_trusted_canisters() -> Vec<TrustedCanisterResponse> {
+        Self::get_all()
+            .into_iter()
+            .map(|(canister_id, entry)| entry.to_response(canister_id))
+            .collect()
+    }
+}
@@ -0,0 +1,110 @@
+use candid::{CandidType, Nat};
+use icrc_ledger_types::{icrc1::account::Account, icrc3::transactions::Transaction};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AccountTransactionKind {
+    Mint,
+    Burn,
+    Transfer,
+    Approve,
+    Unknown,
+}
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionDirection {
+    Incoming,
+    Outgoing,
+    /// A transfer between two subaccounts of the same account, or an approve.
+    Internal,
+}
+
+/// A ledger transaction from the perspective of a single account.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AccountTransaction {
+    pub id: u64,
+    pub kind: AccountTransactionKind,
+    pub direction: TransactionDirection,
+    pub from: Option<Account>,
+    pub to: Option<Account>,
+    pub spender: Option<Account>,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+    pub timestamp: u64,
+}
+
+impl AccountTransaction {
+    pub fn from_transaction(id: u64, account: &Account, transaction: Transaction) -> Self {
+        let direction = |from: Option<&Account>, to: Option<&Account>| match (from, to) {
+            (Some(from), Some(to)) if from == account && to == account => {
+                TransactionDirection::Internal
+            }
+            (Some(from), _) if from == account => TransactionDirection::Outgoing,
+            (_, Some(to)) if to == account => TransactionDirection::Incoming,
+            _ => TransactionDirection::Internal,
+        };
+
+        let mut result = Self {
+            id,
+            kind: AccountTransactionKind::Unknown,
+            direction: TransactionDirection::Internal,
+            from: None,
+            to: None,
+            spender: None,
+            amount: Nat::from(0u64),
+            fee: None,
+            memo: None,
+            created_at_time: None,
+            timestamp: transaction.timestamp,
+        };
+
+        if let Some(mint) = transaction.mint {
+            result.kind = AccountTransactionKind::Mint;
+            result.direction = direction(None, Some(&mint.to));
+            result.to = Some(mint.to);
+            result.amount = mint.amount;
+            result.memo = mint.memo.map(|memo| memo.0.into_vec());
+            result.created_at_time = mint.created_at_time;
+        } else if let Some(burn) = transaction.burn {
+            result.kind = AccountTransactionKind::Burn;
+            result.direction = direction(Some(&burn.from), None);
+            result.from = Some(burn.from);
+            result.spender = burn.spender;
+            result.amount = burn.amount;
+            result.memo = burn.memo.map(|memo| memo.0.into_vec());
+            result.created_at_time = burn.created_at_time;
+        } else if let Some(transfer) = transaction.transfer {
+            result.kind = AccountTransactionKind::Transfer;
+            result.direction = direction(Some(&transfer.from), Some(&transfer.to));
+            result.from = Some(transfer.from);
+            result.to = Some(transfer.to);
+            result.spender = transfer.spender;
+            result.amount = transfer.amount;
+            result.fee = transfer.fee;
+            result.memo = transfer.memo.map(|memo| memo.0.into_vec());
+            result.created_at_time = transfer.created_at_time;
+        } else if let Some(approve) = transaction.approve {
+            result.kind = AccountTransactionKind::Approve;
+            result.from = Some(approve.from);
+            result.spender = Some(approve.spender);
+            result.amount = approve.amount;
+            result.fee = approve.fee;
+            result.memo = approve.memo.map(|memo| memo.0.into_vec());
+            result.created_at_time = approve.created_at_time;
+        }
+
+        result
+    }
+}
+
+/// A page of account history, newest first.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AccountTransactionsPage {
+    pub balance: Nat,
+    pub transactions: Vec<AccountTransaction>,
+    pub oldest_tx_id: Option<u64>,
+    /// Pass as `start` to fetch the next (older) page, `None` when the oldest transaction is reached.
+    pub next_start: Option<u64>,
+}
@@ -1,10 +1,23 @@
 use std::fmt::{self, Display};
 
 use candid::CandidType;
-use ic_cdk::api::time;
+use ic_ledger_types::TransferError as IcpTransferError;
+use icrc_ledger_types::{
+    icrc1::transfer::TransferError as Icrc1TransferError,
+    icrc2::{approve::ApproveError, transfer_from::TransferFromError},
+};
 use serde::{Deserialize, Serialize};
 
-use super::validation::ValidationResponse;
+use crate::{
+    cycles_ledger::{CreateCanisterError as CyclesLedgerCreateCanisterError, WithdrawError},
+    cycles_minting::NotifyError,
+    error_telemetry::ErrorTelemetry,
+    misc::runtime::time,
+};
+
+use super::{
+    canister_call_error::CanisterCallError, error_code::ErrorParam, validation::ValidationResponse,
+};
 
 #[derive(Clone, CandidType, Debug, Serialize, Deserialize)]
 pub struct ApiError {
@@ -14,9 +27,21 @@ pub struct ApiError {
     source: Option<String>,
     error_type: ApiErrorType,
     info: Option<Vec<String>>,
+    causes: Option<Vec<ApiErrorCause>>,
+    code: u32,
+    params: Option<Vec<ErrorParam>>,
     timestamp: u64,
 }
 
+/// An error that led to an `ApiError`, ordered from the most recent to the root cause.
+#[derive(Clone, CandidType, Debug, Serialize, Deserialize)]
+pub struct ApiErrorCause {
+    pub error_type: ApiErrorType,
+    pub message: String,
+    pub method_name: Option<String>,
+    pub source: Option<String>,
+}
+
 impl ApiError {
     pub fn new(error_type: ApiErrorType, message: &str) -> Box<Self> {
         Box::new(ApiError {
@@ -24,8 +49,11 @@ impl ApiError {
             message: message.to_string(),
             method_name: None,
             source: None,
-            error_type,
             info: None,
+            causes: None,
+            code: error_type.code(),
+            params: None,
+            error_type,
             timestamp: time(),
         })
     }
@@ -124,6 +152,87 @@ impl ApiError {
         self.method_name = Some(method_name.to_string());
         Box::new(self)
     }
+
+    /// Add a named parameter that is used to render a localized message for the error code.
+    pub fn add_param<K: Display, V: Display>(mut self, key: K, value: V) -> Box<Self> {
+        let mut params = self.params.unwrap_or_default();
+        params.push(ErrorParam::new(key, value));
+        self.params = Some(params);
+        Box::new(self)
+    }
+
+    /// Append an error to the cause chain, including the causes of that error.
+    pub fn add_cause(mut self, cause: ApiError) -> Box<Self> {
+        let mut causes = self.causes.unwrap_or_default();
+        causes.push(cause.to_cause());
+        causes.extend(cause.causes.unwrap_or_default());
+        self.causes = Some(causes);
+        Box::new(self)
+    }
+
+    /// Wrap the error in a new error with the same type and the given message,
+    /// the current error becomes the first cause of the chain.
+    ///
+    /// The tag and source are kept, use `add_method_name` on the result to describe where the context was added.
+    pub fn with_context<S: Display>(self, message: S) -> Box<Self> {
+        let mut error = ApiError::new(self.error_type.clone(), &message.to_string());
+        error.tag = self.tag.clone();
+        error.source = self.source.clone();
+        error.add_cause(self)
+    }
+
+    pub fn causes(&self) -> Vec<ApiErrorCause> {
+        self.causes.clone().unwrap_or_default()
+    }
+
+    /// The deepest error of the chain, or the error itself if it has no causes.
+    pub fn root_cause(&self) -> ApiErrorCause {
+        self.causes
+            .as_ref()
+            .and_then(|causes| causes.last().cloned())
+            .unwrap_or_else(|| self.to_cause())
+    }
+
+    fn to_cause(&self) -> ApiErrorCause {
+        ApiErrorCause {
+            error_type: self.error_type.clone(),
+            message: self.message.clone(),
+            method_name: self.method_name.clone(),
+            source: self.source.clone(),
+        }
+    }
+
+    pub fn error_type(&self) -> &ApiErrorType {
+        &self.error_type
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn code(&self) -> u32 {
+        self.code
+    }
+
+    pub fn params(&self) -> Vec<ErrorParam> {
+        self.params.clone().unwrap_or_default()
+    }
+
+    pub fn method_name(&self) -> Option<&str> {
+        self.method_name.as_deref()
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Report the error to the registered `ErrorTelemetry`, does nothing when telemetry is not enabled.
+    pub fn record(self) -> Box<Self> {
+        if let Some(telemetry) = ErrorTelemetry::registered() {
+            telemetry.record(&self);
+        }
+        Box::new(self)
+    }
 }
 
 #[derive(Clone, CandidType, Debug, Deserialize, Serialize)]
@@ -144,6 +253,261 @@ pub enum ApiErrorType {
     Forbidden,
     ExternalServiceError,
     Deprecated,
+    IcpTransferError(IcpTransferError),
+    Icrc1TransferError(Icrc1TransferError),
+    Icrc2TransferFromError(TransferFromError),
+    Icrc2ApproveError(ApproveError),
+    NotifyError(NotifyError),
+    CanisterCallError(CanisterCallError),
+    CyclesLedgerWithdrawError(WithdrawError),
+    CyclesLedgerCreateCanisterError(CyclesLedgerCreateCanisterError),
+}
+
+/// Stable machine-readable codes of the error types, the numeric values must never change.
+impl ApiErrorType {
+    pub const NOT_IMPLEMENTED: u32 = 1000;
+    pub const UNEXPECTED: u32 = 1001;
+    pub const UNAUTHORIZED: u32 = 1002;
+    pub const NOT_FOUND: u32 = 1003;
+    pub const BAD_REQUEST: u32 = 1004;
+    pub const UNSUPPORTED: u32 = 1005;
+    pub const DUPLICATE: u32 = 1006;
+    pub const VALIDATION_ERROR: u32 = 1007;
+    pub const SERIALIZE_ERROR: u32 = 1008;
+    pub const DESERIALIZE_ERROR: u32 = 1009;
+    pub const PAYLOAD_TOO_LARGE: u32 = 1010;
+    pub const SERVICE_UNAVAILABLE: u32 = 1011;
+    pub const CONFLICT: u32 = 1012;
+    pub const FORBIDDEN: u32 = 1013;
+    pub const EXTERNAL_SERVICE_ERROR: u32 = 1014;
+    pub const DEPRECATED: u32 = 1015;
+    pub const ICP_TRANSFER_ERROR: u32 = 2000;
+    pub const ICRC1_TRANSFER_ERROR: u32 = 2001;
+    pub const ICRC2_TRANSFER_FROM_ERROR: u32 = 2002;
+    pub const ICRC2_APPROVE_ERROR: u32 = 2003;
+    pub const NOTIFY_ERROR: u32 = 2004;
+    pub const CANISTER_CALL_ERROR: u32 = 2005;
+    pub const CYCLES_LEDGER_WITHDRAW_ERROR: u32 = 2006;
+    pub const CYCLES_LEDGER_CREATE_CANISTER_ERROR: u32 = 2007;
+
+    pub fn code(&self) -> u32 {
+        use ApiErrorType::*;
+        match self {
+            NotImplemented => Self::NOT_IMPLEMENTED,
+            Unexpected => Self::UNEXPECTED,
+            Unauthorized => Self::UNAUTHORIZED,
+            NotFound => Self::NOT_FOUND,
+            BadRequest => Self::BAD_REQUEST,
+            Unsupported => Self::UNSUPPORTED,
+            Duplicate => Self::DUPLICATE,
+            ValidationError(_) => Self::VALIDATION_ERROR,
+            SerializeError => Self::SERIALIZE_ERROR,
+            DeserializeError => Self::DESERIALIZE_ERROR,
+            PayloadTooLarge => Self::PAYLOAD_TOO_LARGE,
+            ServiceUnavailable => Self::SERVICE_UNAVAILABLE,
+            Conflict => Self::CONFLICT,
+            Forbidden => Self::FORBIDDEN,
+            ExternalServiceError => Self::EXTERNAL_SERVICE_ERROR,
+            Deprecated => Self::DEPRECATED,
+            IcpTransferError(_) => Self::ICP_TRANSFER_ERROR,
+            Icrc1TransferError(_) => Self::ICRC1_TRANSFER_ERROR,
+            Icrc2TransferFromError(_) => Self::ICRC2_TRANSFER_FROM_ERROR,
+            Icrc2ApproveError(_) => Self::ICRC2_APPROVE_ERROR,
+            NotifyError(_) => Self::NOTIFY_ERROR,
+            CanisterCallError(_) => Self::CANISTER_CALL_ERROR,
+            CyclesLedgerWithdrawError(_) => Self::CYCLES_LEDGER_WITHDRAW_ERROR,
+            CyclesLedgerCreateCanisterError(_) => Self::CYCLES_LEDGER_CREATE_CANISTER_ERROR,
+        }
+    }
+
+    /// The values carried by a ledger, CMC or call error, e.g. `balance` or `expected_fee`,
+    /// so a localized template can render them.
+    pub fn params(&self) -> Vec<ErrorParam> {
+        match self {
+            ApiErrorType::IcpTransferError(err) => match err {
+                IcpTransferError::BadFee { expected_fee } => {
+                    vec![ErrorParam::new("expected_fee", expected_fee.e8s())]
+                }
+                IcpTransferError::InsufficientFunds { balance } => {
+                    vec![ErrorParam::new("balance", balance.e8s())]
+                }
+                IcpTransferError::TxTooOld {
+                    allowed_window_nanos,
+                } => vec![ErrorParam::new(
+                    "allowed_window_nanos",
+                    allowed_window_nanos,
+                )],
+                IcpTransferError::TxDuplicate { duplicate_of } => {
+                    vec![ErrorParam::new("duplicate_of", duplicate_of)]
+                }
+                IcpTransferError::TxCreatedInFuture => vec![],
+            },
+            ApiErrorType::Icrc1TransferError(err) => match err {
+                Icrc1TransferError::BadFee { expected_fee } => {
+                    vec![ErrorParam::new("expected_fee", expected_fee)]
+                }
+                Icrc1TransferError::BadBurn { min_burn_amount } => {
+                    vec![ErrorParam::new("min_burn_amount", min_burn_amount)]
+                }
+                Icrc1TransferError::InsufficientFunds { balance } => {
+                    vec![ErrorParam::new("balance", balance)]
+                }
+                Icrc1TransferError::CreatedInFuture { ledger_time } => {
+                    vec![ErrorParam::new("ledger_time", ledger_time)]
+                }
+                Icrc1TransferError::Duplicate { duplicate_of } => {
+                    vec![ErrorParam::new("duplicate_of", duplicate_of)]
+                }
+                Icrc1TransferError::GenericError {
+                    error_code,
+                    message,
+                } => generic_params(error_code, message),
+                Icrc1TransferError::TooOld | Icrc1TransferError::TemporarilyUnavailable => vec![],
+            },
+            ApiErrorType::Icrc2TransferFromError(err) => match err {
+                TransferFromError::BadFee { expected_fee } => {
+                    vec![ErrorParam::new("expected_fee", expected_fee)]
+                }
+                TransferFromError::BadBurn { min_burn_amount } => {
+                    vec![ErrorParam::new("min_burn_amount", min_burn_amount)]
+                }
+                TransferFromError::InsufficientFunds { balance } => {
+                    vec![ErrorParam::new("balance", balance)]
+                }
+                TransferFromError::InsufficientAllowance { allowance } => {
+                    vec![ErrorParam::new("allowance", allowance)]
+                }
+                TransferFromError::CreatedInFuture { ledger_time } => {
+                    vec![ErrorParam::new("ledger_time", ledger_time)]
+                }
+                TransferFromError::Duplicate { duplicate_of } => {
+                    vec![ErrorParam::new("duplicate_of", duplicate_of)]
+                }
+                TransferFromError::GenericError {
+                    error_code,
+                    message,
+                } => generic_params(error_code, message),
+                TransferFromError::TooOld | TransferFromError::TemporarilyUnavailable => vec![],
+            },
+            ApiErrorType::Icrc2ApproveError(err) => match err {
+                ApproveError::BadFee { expected_fee } => {
+                    vec![ErrorParam::new("expected_fee", expected_fee)]
+                }
+                ApproveError::InsufficientFunds { balance } => {
+                    vec![ErrorParam::new("balance", balance)]
+                }
+                ApproveError::AllowanceChanged { current_allowance } => {
+                    vec![ErrorParam::new("current_allowance", current_allowance)]
+                }
+                ApproveError::Expired { ledger_time }
+                | ApproveError::CreatedInFuture { ledger_time } => {
+                    vec![ErrorParam::new("ledger_time", ledger_time)]
+                }
+                ApproveError::Duplicate { duplicate_of } => {
+                    vec![ErrorParam::new("duplicate_of", duplicate_of)]
+                }
+                ApproveError::GenericError {
+                    error_code,
+                    message,
+                } => generic_params(error_code, message),
+                ApproveError::TooOld | ApproveError::TemporarilyUnavailable => vec![],
+            },
+            ApiErrorType::NotifyError(err) => match err {
+                NotifyError::Refunded {
+                    block_index,
+                    reason,
+                } => {
+                    let mut params = vec![ErrorParam::new("reason", reason)];
+                    if let Some(block_index) = block_index {
+                        params.push(ErrorParam::new("block_index", block_index));
+                    }
+                    params
+                }
+                NotifyError::InvalidTransaction(reason) => vec![ErrorParam::new("reason", reason)],
+                NotifyError::Other {
+                    error_message,
+                    error_code,
+                } => generic_params(error_code, error_message),
+                NotifyError::TransactionTooOld(block_index) => {
+                    vec![ErrorParam::new("block_index", block_index)]
+                }
+                NotifyError::Processing => vec![],
+            },
+            ApiErrorType::CanisterCallError(err) => match err {
+                CanisterCallError::InsufficientCycles {
+                    available,
+                    required,
+                } => vec![
+                    ErrorParam::new("available", available),
+                    ErrorParam::new("required", required),
+                ],
+                CanisterCallError::Rejected {
+                    reject_code,
+                    reject_message,
+                } => vec![
+                    ErrorParam::new("reject_code", format!("{:?}", reject_code)),
+                    ErrorParam::new("reject_message", reject_message),
+                ],
+                CanisterCallError::CandidDecodeFailed(message) => {
+                    vec![ErrorParam::new("message", message)]
+                }
+                CanisterCallError::CallPerformFailed => vec![],
+            },
+            ApiErrorType::CyclesLedgerWithdrawError(err) => match err {
+                WithdrawError::BadFee { expected_fee } => {
+                    vec![ErrorParam::new("expected_fee", expected_fee)]
+                }
+                WithdrawError::InsufficientFunds { balance } => {
+                    vec![ErrorParam::new("balance", balance)]
+                }
+                WithdrawError::CreatedInFuture { ledger_time } => {
+                    vec![ErrorParam::new("ledger_time", ledger_time)]
+                }
+                WithdrawError::Duplicate { duplicate_of } => {
+                    vec![ErrorParam::new("duplicate_of", duplicate_of)]
+                }
+                WithdrawError::FailedToWithdraw {
+                    rejection_reason, ..
+                } => vec![ErrorParam::new("reason", rejection_reason)],
+                WithdrawError::GenericError {
+                    error_code,
+                    message,
+                } => generic_params(error_code, message),
+                WithdrawError::InvalidReceiver { receiver } => {
+                    vec![ErrorParam::new("receiver", receiver)]
+                }
+                WithdrawError::TooOld | WithdrawError::TemporarilyUnavailable => vec![],
+            },
+            ApiErrorType::CyclesLedgerCreateCanisterError(err) => match err {
+                CyclesLedgerCreateCanisterError::InsufficientFunds { balance } => {
+                    vec![ErrorParam::new("balance", balance)]
+                }
+                CyclesLedgerCreateCanisterError::CreatedInFuture { ledger_time } => {
+                    vec![ErrorParam::new("ledger_time", ledger_time)]
+                }
+                CyclesLedgerCreateCanisterError::Duplicate { duplicate_of, .. } => {
+                    vec![ErrorParam::new("duplicate_of", duplicate_of)]
+                }
+                CyclesLedgerCreateCanisterError::FailedToCreate { error, .. } => {
+                    vec![ErrorParam::new("reason", error)]
+                }
+                CyclesLedgerCreateCanisterError::GenericError {
+                    error_code,
+                    message,
+                } => generic_params(error_code, message),
+                CyclesLedgerCreateCanisterError::TooOld
+                | CyclesLedgerCreateCanisterError::TemporarilyUnavailable => vec![],
+            },
+            _ => vec![],
+        }
+    }
+}
+
+fn generic_params<C: Display, M: Display>(error_code: C, message: M) -> Vec<ErrorParam> {
+    vec![
+        ErrorParam::new("error_code", error_code),
+        ErrorParam::new("message", message),
+    ]
 }
 
 impl fmt::Display for ApiError {
@@ -152,6 +516,21 @@ impl fmt::Display for ApiError {
             f,
             "ApiError: tag: {:?}, message: {:?}, method_name: {:?}, error_type: {:?}, info: {:?}",
             self.tag, self.message, self.method_name, self.error_type, self.info
+        )?;
+
+        for cause in self.causes.iter().flatten() {
+            write!(f, "\n  caused by: {}", cause)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ApiErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:?}, method_name: {:?}, source: {:?}",
+            self.error_type, self.message, self.method_name, self.source
         )
     }
 }
@@ -176,6 +555,82 @@ impl fmt::Display for ApiErrorType {
             Forbidden => write!(f, "Forbidden"),
             ExternalServiceError => write!(f, "ExternalServiceError"),
             Deprecated => write!(f, "Deprecated"),
+            IcpTransferError(_) => write!(f, "IcpTransferError"),
+            Icrc1TransferError(_) => write!(f, "Icrc1TransferError"),
+            Icrc2TransferFromError(_) => write!(f, "Icrc2TransferFromError"),
+            Icrc2ApproveError(_) => write!(f, "Icrc2ApproveError"),
+            NotifyError(_) => write!(f, "NotifyError"),
+            CanisterCallError(_) => write!(f, "CanisterCallError"),
+            CyclesLedgerWithdrawError(_) => write!(f, "CyclesLedgerWithdrawError"),
+            CyclesLedgerCreateCanisterError(_) => write!(f, "CyclesLedgerCreateCanisterError"),
+        }
+    }
+}
+
+macro_rules! impl_api_error_from {
+    ($type:ty, $variant:ident) => {
+        impl From<$type> for ApiError {
+            fn from(err: $type) -> Self {
+                let message = format!("{:?}", err);
+                let mut error = ApiError::new(ApiErrorType::$variant(err.into()), &message);
+                let params = error.error_type.params();
+                if !params.is_empty() {
+                    error.params = Some(params);
+                }
+                *error
+            }
         }
+
+        impl From<$type> for Box<ApiError> {
+            fn from(err: $type) -> Self {
+                Box::new(ApiError::from(err))
+            }
+        }
+    };
+}
+
+impl_api_error_from!(IcpTransferError, IcpTransferError);
+impl_api_error_from!(Icrc1TransferError, Icrc1TransferError);
+impl_api_error_from!(TransferFromError, Icrc2TransferFromError);
+impl_api_error_from!(ApproveError, Icrc2ApproveError);
+impl_api_error_from!(NotifyError, NotifyError);
+impl_api_error_from!(CanisterCallError, CanisterCallError);
+impl_api_error_from!(WithdrawError, CyclesLedgerWithdrawError);
+impl_api_error_from!(
+    CyclesLedgerCreateCanisterError,
+    CyclesLedgerCreateCanisterError
+);
+impl_api_error_from!(ic_cdk::call::Error, CanisterCallError);
+impl_api_error_from!(ic_cdk::call::CallFailed, CanisterCallError);
+impl_api_error_from!(ic_cdk::call::CandidDecodeFailed, CanisterCallError);
+
+// `CyclesMintingService` is generated against the deprecated call api
+#[allow(deprecated)]
+mod legacy {
+    use ic_cdk::api::call::RejectionCode;
+
+    use super::{ApiError, ApiErrorType};
+
+    impl_api_error_from!((RejectionCode, String), CanisterCallError);
+}
+
+#[cfg(test)]
+mod tests {
+    use candid::Nat;
+
+    use super::*;
+    use crate::misc::runtime::MockRuntime;
+
+    #[test]
+    fn typed_errors_carry_their_code_and_values() {
+        MockRuntime::new().install();
+
+        let error = ApiError::from(Icrc1TransferError::InsufficientFunds {
+            balance: Nat::from(42u64),
+        });
+
+        assert_eq!(error.code(), ApiErrorType::ICRC1_TRANSFER_ERROR);
+        assert_eq!(error.params(), vec![ErrorParam::new("balance", 42)]);
+        assert_eq!(ApiError::not_found("").code(), ApiErrorType::NOT_FOUND);
     }
 }
@@ -0,0 +1,60 @@
+use std::fmt;
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::impl_storable_for;
+
+impl_storable_for!(BookBalance);
+
+pub static REVENUE_PROJECT_FEES: &str = "project_fees";
+pub static REVENUE_CANISTER_FEES: &str = "canister_fees";
+
+/// An account of the internal double-entry ledger.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BookAccount {
+    /// What the canister owes a principal (liability, credit normal)
+    User(Principal),
+    /// The funds held by the canister on-chain (asset, debit normal)
+    Treasury,
+    /// Fees earned, e.g. `REVENUE_PROJECT_FEES` (revenue, credit normal)
+    Revenue(String),
+    Custom(String),
+}
+
+impl BookAccount {
+    /// The key of the account in the balances storage
+    pub fn key(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for BookAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookAccount::User(principal) => write!(f, "user:{}", principal),
+            BookAccount::Treasury => write!(f, "treasury"),
+            BookAccount::Revenue(name) => write!(f, "revenue:{}", name),
+            BookAccount::Custom(name) => write!(f, "custom:{}", name),
+        }
+    }
+}
+
+/// The running totals of a book account.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct BookBalance {
+    pub debits_e8s: u128,
+    pub credits_e8s: u128,
+}
+
+impl BookBalance {
+    /// Debits minus credits, positive for assets
+    pub fn debit_balance(&self) -> i128 {
+        self.debits_e8s as i128 - self.credits_e8s as i128
+    }
+
+    /// Credits minus debits, positive for liabilities and revenue
+    pub fn credit_balance(&self) -> i128 {
+        self.credits_e8s as i128 - self.debits_e8s as i128
+    }
+}
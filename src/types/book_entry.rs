@@ -0,0 +1,98 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::ApiError,
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+    result::CanisterResult,
+};
+
+use super::book_account::BookAccount;
+
+impl_storable_for!(BookEntry);
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BookLine {
+    pub account: BookAccount,
+    pub debit_e8s: u64,
+    pub credit_e8s: u64,
+}
+
+impl BookLine {
+    pub fn debit(account: BookAccount, amount_e8s: u64) -> Self {
+        Self {
+            account,
+            debit_e8s: amount_e8s,
+            credit_e8s: 0,
+        }
+    }
+
+    pub fn credit(account: BookAccount, amount_e8s: u64) -> Self {
+        Self {
+            account,
+            debit_e8s: 0,
+            credit_e8s: amount_e8s,
+        }
+    }
+}
+
+/// A balanced journal entry of the internal ledger, optionally linked to an on-chain block.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BookEntry {
+    pub lines: Vec<BookLine>,
+    pub description: String,
+    pub block_index: Option<u64>,
+    pub created_by: Principal,
+    pub created_at: Time,
+}
+
+impl BookEntry {
+    pub fn new(
+        lines: Vec<BookLine>,
+        description: &str,
+        block_index: Option<u64>,
+        created_by: Principal,
+    ) -> Self {
+        Self {
+            lines,
+            description: description.to_string(),
+            block_index,
+            created_by,
+            created_at: time(),
+        }
+    }
+
+    pub fn total_debits(&self) -> u128 {
+        self.lines.iter().map(|line| line.debit_e8s as u128).sum()
+    }
+
+    pub fn total_credits(&self) -> u128 {
+        self.lines.iter().map(|line| line.credit_e8s as u128).sum()
+    }
+
+    /// An entry needs at least two lines, every line is either a debit or a credit and debits equal credits
+    pub fn validate(&self) -> CanisterResult<()> {
+        let invalid = |message: &str| {
+            Err(ApiError::bad_request(message)
+                .add_method_name("validate")
+                .add_info("book_entry")
+                .add_source("toolkit_utils"))
+        };
+
+        if self.lines.len() < 2 {
+            return invalid("Entry needs at least two lines");
+        }
+        if self
+            .lines
+            .iter()
+            .any(|line| (line.debit_e8s == 0) == (line.credit_e8s == 0))
+        {
+            return invalid("Every line must be either a debit or a credit");
+        }
+        if self.total_debits() != self.total_credits() {
+            return invalid("Debits do not equal credits");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,17 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::misc::generic::Time;
+
+/// An ICP/XDR conversion rate of the CMC as cached by the rate provider.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CachedRate {
+    pub cycles_minting_canister_id: Principal,
+    pub xdr_permyriad_per_icp: u64,
+    /// The time the CMC set the rate
+    pub timestamp_seconds: u64,
+    /// The time the rate was fetched from the CMC
+    pub fetched_at: Time,
+    /// Whether the certificate of the response was verified
+    pub certified: bool,
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+
+use candid::CandidType;
+#[allow(deprecated)]
+use ic_cdk::api::call::RejectionCode;
+use ic_cdk::call::{CallFailed, CallRejected, CandidDecodeFailed, Error};
+use serde::{Deserialize, Serialize};
+
+/// Reject codes of a failed inter-canister call, see the IC interface specification.
+#[derive(Clone, Copy, CandidType, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RejectCode {
+    SysFatal,
+    SysTransient,
+    DestinationInvalid,
+    CanisterReject,
+    CanisterError,
+    SysUnknown,
+    Unrecognized(u32),
+}
+
+impl From<u32> for RejectCode {
+    fn from(code: u32) -> Self {
+        use RejectCode::*;
+        match code {
+            1 => SysFatal,
+            2 => SysTransient,
+            3 => DestinationInvalid,
+            4 => CanisterReject,
+            5 => CanisterError,
+            6 => SysUnknown,
+            code => Unrecognized(code),
+        }
+    }
+}
+
+// `CyclesMintingService` still uses the legacy call api
+#[allow(deprecated)]
+impl From<RejectionCode> for RejectCode {
+    fn from(code: RejectionCode) -> Self {
+        RejectCode::from(code as u32)
+    }
+}
+
+#[derive(Clone, CandidType, Debug, Serialize, Deserialize)]
+pub enum CanisterCallError {
+    InsufficientCycles {
+        available: u128,
+        required: u128,
+    },
+    CallPerformFailed,
+    Rejected {
+        reject_code: RejectCode,
+        reject_message: String,
+    },
+    CandidDecodeFailed(String),
+}
+
+impl CanisterCallError {
+    /// Whether the call might succeed when it is retried.
+    ///
+    /// `SysUnknown` is excluded because the state of the callee is unknown, the caller
+    /// has to make sure a retry is safe (for example by using ledger deduplication).
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            CanisterCallError::CallPerformFailed
+                | CanisterCallError::InsufficientCycles { .. }
+                | CanisterCallError::Rejected {
+                    reject_code: RejectCode::SysTransient,
+                    ..
+                }
+        )
+    }
+
+    /// Whether a retry is safe when the call is deduplicated by the callee, like a ledger transfer
+    /// with a fixed `created_at_time`. A duplicate of a call with an unknown outcome is rejected.
+    pub fn is_retryable_with_deduplication(&self) -> bool {
+        self.is_transient()
+            || matches!(
+                self,
+                CanisterCallError::Rejected {
+                    reject_code: RejectCode::SysUnknown,
+                    ..
+                }
+            )
+    }
+}
+
+impl From<CallRejected> for CanisterCallError {
+    fn from(err: CallRejected) -> Self {
+        CanisterCallError::Rejected {
+            reject_code: RejectCode::from(err.raw_reject_code()),
+            reject_message: err.reject_message().to_string(),
+        }
+    }
+}
+
+impl From<CallFailed> for CanisterCallError {
+    fn from(err: CallFailed) -> Self {
+        CanisterCallError::from(Error::from(err))
+    }
+}
+
+impl From<CandidDecodeFailed> for CanisterCallError {
+    fn from(err: CandidDecodeFailed) -> Self {
+        CanisterCallError::CandidDecodeFailed(err.to_string())
+    }
+}
+
+impl From<Error> for CanisterCallError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::InsufficientLiquidCycleBalance(err) => CanisterCallError::InsufficientCycles {
+                available: err.available,
+                required: err.required,
+            },
+            Error::CallPerformFailed(_) => CanisterCallError::CallPerformFailed,
+            Error::CallRejected(err) => CanisterCallError::from(err),
+            Error::CandidDecodeFailed(err) => CanisterCallError::from(err),
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl From<(RejectionCode, String)> for CanisterCallError {
+    fn from((reject_code, reject_message): (RejectionCode, String)) -> Self {
+        CanisterCallError::Rejected {
+            reject_code: RejectCode::from(reject_code),
+            reject_message,
+        }
+    }
+}
+
+impl fmt::Display for CanisterCallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use CanisterCallError::*;
+        match self {
+            InsufficientCycles {
+                available,
+                required,
+            } => write!(
+                f,
+                "Insufficient cycles - available: {}, required: {}",
+                available, required
+            ),
+            CallPerformFailed => write!(f, "Call perform failed"),
+            Rejected {
+                reject_code,
+                reject_message,
+            } => write!(f, "Call rejected - {:?}: {}", reject_code, reject_message),
+            CandidDecodeFailed(message) => write!(f, "Candid decode failed - {}", message),
+        }
+    }
+}
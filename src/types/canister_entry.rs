@@ -1,8 +1,10 @@
 use candid::{CandidType, Principal};
-use ic_cdk::api::time;
 use serde::{Deserialize, Serialize};
 
-use crate::{impl_storable_for, misc::generic::Time};
+use crate::{
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+};
 
 use super::version::Version;
 
@@ -0,0 +1,43 @@
+use candid::CandidType;
+use ic_ledger_types::{AccountIdentifier, BlockIndex};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+};
+
+impl_storable_for!(ConsumedPayment);
+
+/// A verified incoming ICP payment, stored by block index so it can only be claimed once.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ConsumedPayment {
+    pub block_index: BlockIndex,
+    pub from: AccountIdentifier,
+    pub to: AccountIdentifier,
+    pub amount_e8s: u64,
+    pub memo: u64,
+    pub block_timestamp: Time,
+    pub consumed_at: Time,
+}
+
+impl ConsumedPayment {
+    pub fn new(
+        block_index: BlockIndex,
+        from: AccountIdentifier,
+        to: AccountIdentifier,
+        amount_e8s: u64,
+        memo: u64,
+        block_timestamp: Time,
+    ) -> Self {
+        Self {
+            block_index,
+            from,
+            to,
+            amount_e8s,
+            memo,
+            block_timestamp,
+            consumed_at: time(),
+        }
+    }
+}
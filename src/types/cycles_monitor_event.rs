@@ -0,0 +1,50 @@
+use candid::{CandidType, Nat, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::ApiError,
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+};
+
+use super::monitored_canister::TopUpMethod;
+
+impl_storable_for!(CyclesMonitorEvent);
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub enum CyclesMonitorAction {
+    StatusFetched {
+        cycles: u128,
+        burn_rate_cycles_per_day: Option<u128>,
+    },
+    StatusFailed {
+        error: Box<ApiError>,
+    },
+    ToppedUp {
+        method: TopUpMethod,
+        /// The cycles minted by the CMC or deposited
+        cycles: Nat,
+    },
+    TopUpFailed {
+        method: TopUpMethod,
+        error: Box<ApiError>,
+    },
+}
+
+/// Log entry of every status fetch and top-up of the `CyclesMonitor`.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub struct CyclesMonitorEvent {
+    pub canister_id: Principal,
+    pub action: CyclesMonitorAction,
+    pub created_at: Time,
+}
+
+impl CyclesMonitorEvent {
+    pub fn new(canister_id: Principal, action: CyclesMonitorAction) -> Self {
+        Self {
+            canister_id,
+            action,
+            created_at: time(),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use candid::CandidType;
+use ic_cdk::management_canister::CanisterStatusResult;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    conversions::{try_nat_to_u128, try_nat_to_u64},
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+    result::CanisterResult,
+};
+
+impl_storable_for!(CyclesSnapshot);
+
+/// A `canister_status` sample of a canister, stored by `(canister_id, taken_at)`.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CyclesSnapshot {
+    pub cycles: u128,
+    pub memory_size: u64,
+    pub idle_cycles_burned_per_day: u128,
+    pub freezing_threshold_seconds: u64,
+    pub taken_at: Time,
+}
+
+impl CyclesSnapshot {
+    pub fn from_status(status: &CanisterStatusResult) -> CanisterResult<Self> {
+        Ok(Self {
+            cycles: try_nat_to_u128(&status.cycles)?,
+            memory_size: try_nat_to_u64(&status.memory_size)?,
+            idle_cycles_burned_per_day: try_nat_to_u128(&status.idle_cycles_burned_per_day)?,
+            freezing_threshold_seconds: try_nat_to_u64(&status.settings.freezing_threshold)?,
+            taken_at: time(),
+        })
+    }
+
+    /// The balance below which the canister is frozen, based on its idle burn
+    pub fn freezing_threshold_cycles(&self) -> u128 {
+        self.idle_cycles_burned_per_day
+            .saturating_mul(u128::from(self.freezing_threshold_seconds))
+            / 86_400
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt::Display;
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// A named value that is used to render a localized error message, e.g. `{min}`.
+#[derive(Clone, CandidType, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ErrorParam {
+    pub key: String,
+    pub value: String,
+}
+
+impl ErrorParam {
+    pub fn new<K: Display, V: Display>(key: K, value: V) -> Self {
+        Self {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+/// Stable codes for validation failures, the numeric values must never change.
+#[derive(Clone, Copy, CandidType, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ValidationCode {
+    MinLength,
+    MaxLength,
+    MinCount,
+    MaxCount,
+    InvalidEmail,
+    StartDateAfterEndDate,
+    StartDateInPast,
+}
+
+impl ValidationCode {
+    pub fn code(&self) -> u32 {
+        use ValidationCode::*;
+        match self {
+            MinLength => 3000,
+            MaxLength => 3001,
+            MinCount => 3002,
+            MaxCount => 3003,
+            InvalidEmail => 3004,
+            StartDateAfterEndDate => 3005,
+            StartDateInPast => 3006,
+        }
+    }
+}
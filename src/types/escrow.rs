@@ -0,0 +1,129 @@
+use candid::{CandidType, Nat, Principal};
+use icrc_ledger_types::icrc1::{account::Account, transfer::BlockIndex};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+};
+
+impl_storable_for!(Escrow);
+
+/// `Open -> Funded -> Released | Refunded | Expired`
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum EscrowStatus {
+    /// Waiting for the deposit
+    Open,
+    /// The deposit has been received
+    Funded,
+    Released {
+        block_index: BlockIndex,
+    },
+    Refunded {
+        block_index: BlockIndex,
+    },
+    /// The deadline passed without a deposit that covers the refund fee
+    Expired,
+}
+
+/// A release or refund transfer, stored before the ledger is called so a retry sends the same
+/// transfer (amount and `created_at_time`) and is deduplicated by the ledger.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EscrowPayout {
+    pub to: Account,
+    pub amount: Nat,
+    pub created_at_time: u64,
+}
+
+/// Funds held in a dedicated subaccount of this canister until they are released or refunded.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub struct Escrow {
+    pub ledger_canister_id: Principal,
+    pub subaccount: [u8; 32],
+    pub depositor: Account,
+    pub beneficiary: Account,
+    pub amount: Nat,
+    pub deadline: Time,
+    pub status: EscrowStatus,
+    pub payout: Option<EscrowPayout>,
+    pub created_by: Principal,
+    pub created_at: Time,
+    pub updated_at: Time,
+}
+
+impl Escrow {
+    pub fn new(
+        ledger_canister_id: Principal,
+        subaccount: [u8; 32],
+        depositor: Account,
+        beneficiary: Account,
+        amount: Nat,
+        deadline: Time,
+        created_by: Principal,
+    ) -> Self {
+        Self {
+            ledger_canister_id,
+            subaccount,
+            depositor,
+            beneficiary,
+            amount,
+            deadline,
+            status: EscrowStatus::Open,
+            payout: None,
+            created_by,
+            created_at: time(),
+            updated_at: time(),
+        }
+    }
+
+    pub fn set_status(&mut self, status: EscrowStatus) {
+        self.status = status;
+        self.updated_at = time();
+    }
+
+    pub fn set_payout(&mut self, payout: Option<EscrowPayout>) {
+        self.payout = payout;
+        self.updated_at = time();
+    }
+
+    pub fn is_expired(&self) -> bool {
+        time() >= self.deadline
+    }
+
+    pub fn is_settled(&self) -> bool {
+        matches!(
+            self.status,
+            EscrowStatus::Released { .. } | EscrowStatus::Refunded { .. } | EscrowStatus::Expired
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::runtime::MockRuntime;
+
+    #[test]
+    fn escrow_expires_at_the_deadline() {
+        let runtime = MockRuntime::new();
+        runtime.install();
+        runtime.set_time(1_000);
+
+        let account = Account::from(Principal::from_slice(&[1]));
+        let escrow = Escrow::new(
+            Principal::from_slice(&[2]),
+            [0; 32],
+            account,
+            account,
+            Nat::from(100u64),
+            2_000,
+            Principal::from_slice(&[1]),
+        );
+
+        assert!(!escrow.is_expired());
+        runtime.advance_time(999);
+        assert!(!escrow.is_expired());
+        runtime.advance_time(1);
+        assert!(escrow.is_expired());
+    }
+}
@@ -1,9 +1,12 @@
 use candid::{CandidType, Principal};
-use ic_cdk::api::{msg_caller, time};
 use icrc_ledger_types::icrc1::account::Account;
 use serde::{Deserialize, Serialize};
 
-use crate::{governance_types::ProposalType, impl_storable_for};
+use crate::{
+    governance_types::ProposalType,
+    impl_storable_for,
+    misc::runtime::{msg_caller, time},
+};
 
 use super::governance_types::GovernanceType;
 
@@ -1,10 +1,15 @@
 use std::collections::HashMap;
 
 use candid::{CandidType, Principal};
-use ic_cdk::api::{msg_caller, time};
 use serde::{Deserialize, Serialize};
 
-use crate::{impl_storable_for, misc::generic::Time};
+use crate::{
+    impl_storable_for,
+    misc::{
+        generic::Time,
+        runtime::{msg_caller, time},
+    },
+};
 
 use super::action_value::ActionValue;
 
@@ -1,8 +1,10 @@
 use candid::{CandidType, Principal};
-use ic_cdk::api::{msg_caller, time};
 use serde::{Deserialize, Serialize};
 
-use crate::impl_storable_for;
+use crate::{
+    impl_storable_for,
+    misc::runtime::{msg_caller, time},
+};
 
 use super::action_value::ActionValue;
 
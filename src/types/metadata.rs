@@ -1,9 +1,11 @@
 use candid::{CandidType, Principal};
-use ic_cdk::api::time;
 use icrc_ledger_types::icrc1::account::Account;
 use serde::{Deserialize, Serialize};
 
-use crate::{impl_storable_for, misc::generic::Time};
+use crate::{
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+};
 
 use super::{
     action_value::ActionValue, project_root_init_args::ProjectInitArgs, result::CanisterResult,
@@ -1,18 +1,41 @@
+pub mod account_transaction;
 pub mod action_value;
 pub mod api_error;
+pub mod book_account;
+pub mod book_entry;
+pub mod cached_rate;
+pub mod canister_call_error;
 pub mod canister_entry;
+pub mod consumed_payment;
+pub mod cycles_monitor_event;
+pub mod cycles_snapshot;
 pub mod date_range;
+pub mod error_code;
+pub mod escrow;
 pub mod governance_config;
 pub mod governance_types;
 pub mod icrc_types;
 pub mod log;
 pub mod management_config;
 pub mod metadata;
+pub mod monitored_canister;
+pub mod network_config;
 pub mod paged_response;
 pub mod path_entry;
+pub mod payout_batch;
+pub mod payout_item;
+pub mod pricing_quote;
 pub mod project_registry_entry;
 pub mod project_root_init_args;
 pub mod result;
+pub mod runway_forecast;
+pub mod subaccount_entry;
+pub mod sweep_account;
+pub mod sweep_record;
+pub mod token_amount;
+pub mod transfer_journal_entry;
+pub mod transfer_plan;
+pub mod trusted_canister;
 pub mod validation;
 pub mod version;
 pub mod wasm;
@@ -0,0 +1,63 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+};
+
+impl_storable_for!(MonitoredCanister);
+
+/// How a monitored canister is topped up when its balance drops below the threshold.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum TopUpMethod {
+    /// Convert ICP of the monitoring canister to cycles through the CMC
+    Icp { amount_e8s: u64 },
+    /// Send cycles of the monitoring canister with `deposit_cycles`
+    DepositCycles { cycles: u128 },
+}
+
+/// A canister whose cycles balance is checked by the `CyclesMonitor`.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MonitoredCanister {
+    pub threshold_cycles: u128,
+    pub top_up: TopUpMethod,
+    pub last_cycles: Option<u128>,
+    /// Estimated from the balance change between the last two checks
+    pub burn_rate_cycles_per_day: Option<u128>,
+    pub last_checked_at: Option<Time>,
+    pub last_topped_up_at: Option<Time>,
+    /// An ICP top-up transfer to the CMC whose notify has not succeeded yet
+    pub pending_top_up_block: Option<u64>,
+    pub registered_at: Time,
+}
+
+impl MonitoredCanister {
+    pub fn new(threshold_cycles: u128, top_up: TopUpMethod) -> Self {
+        Self {
+            threshold_cycles,
+            top_up,
+            last_cycles: None,
+            burn_rate_cycles_per_day: None,
+            last_checked_at: None,
+            last_topped_up_at: None,
+            pending_top_up_block: None,
+            registered_at: time(),
+        }
+    }
+
+    pub fn is_due(&self, interval_seconds: u64, now: Time) -> bool {
+        self.last_checked_at
+            .map(|checked_at| {
+                now.saturating_sub(checked_at) >= interval_seconds.saturating_mul(1_000_000_000)
+            })
+            .unwrap_or(true)
+    }
+
+    /// Whether the last top-up happened within the cooldown
+    pub fn is_cooling_down(&self, cooldown_seconds: u64, now: Time) -> bool {
+        self.last_topped_up_at.is_some_and(|topped_up_at| {
+            now.saturating_sub(topped_up_at) < cooldown_seconds.saturating_mul(1_000_000_000)
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use candid::{CandidType, Principal};
+use ic_ledger_types::{MAINNET_CYCLES_MINTING_CANISTER_ID, MAINNET_LEDGER_CANISTER_ID};
+use serde::{Deserialize, Serialize};
+
+use crate::impl_storable_for;
+
+impl_storable_for!(NetworkConfig);
+
+pub static MAINNET_CYCLES_LEDGER_CANISTER_ID: &str = "um5iw-rqaaa-aaaaq-qaaba-cai";
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Local,
+    Custom,
+}
+
+/// Canister ids of the ledger and cycles minting canister that the transaction and cycles helpers talk to.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NetworkConfig {
+    pub network: Network,
+    pub ledger_canister_id: Principal,
+    pub cycles_minting_canister_id: Principal,
+    pub cycles_ledger_canister_id: Principal,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+impl NetworkConfig {
+    pub fn mainnet() -> Self {
+        Self {
+            network: Network::Mainnet,
+            ledger_canister_id: MAINNET_LEDGER_CANISTER_ID,
+            cycles_minting_canister_id: MAINNET_CYCLES_MINTING_CANISTER_ID,
+            cycles_ledger_canister_id: Principal::from_text(MAINNET_CYCLES_LEDGER_CANISTER_ID)
+                .expect("Invalid cycles ledger canister id"),
+        }
+    }
+
+    /// A local replica with the NNS canisters installed by `dfx nns install`,
+    /// which uses the same canister ids as mainnet.
+    pub fn local() -> Self {
+        Self {
+            network: Network::Local,
+            ..Self::mainnet()
+        }
+    }
+
+    /// Locally deployed canisters, for example in PocketIC.
+    pub fn custom(
+        ledger_canister_id: Principal,
+        cycles_minting_canister_id: Principal,
+        cycles_ledger_canister_id: Principal,
+    ) -> Self {
+        Self {
+            network: Network::Custom,
+            ledger_canister_id,
+            cycles_minting_canister_id,
+            cycles_ledger_canister_id,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use candid::{CandidType, Principal};
+use icrc_ledger_types::icrc1::account::Subaccount;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+    payout_item::{PayoutItem, PayoutStatus},
+};
+
+impl_storable_for!(PayoutBatch);
+
+/// The outcome of `PayoutEngine::process_next_chunk`.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PayoutChunkResult {
+    /// A previous chunk is still being processed
+    Busy,
+    /// The number of attempted transfers
+    Processed(u64),
+    /// No pending payouts are left
+    Done,
+}
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PayoutBatchStatus {
+    Pending,
+    Completed,
+    CompletedWithFailures,
+}
+
+/// A list of transfers from one subaccount of this canister, executed in chunks.
+///
+/// The items are stored separately by `(batch id, index)`, the batch only keeps the counts,
+/// so updating one item never re-encodes the others.
+///
+/// Every payout gets its `created_at_time` on its first attempt, a payout that is still pending after the
+/// ledger deduplication window (`PAYOUT_DEDUPLICATION_WINDOW_NANOS`) is failed instead of retried.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub struct PayoutBatch {
+    pub ledger_canister_id: Principal,
+    pub from_subaccount: Option<Subaccount>,
+    pub total: u64,
+    pub completed: u64,
+    pub failed: u64,
+    /// Every item before this index is settled
+    pub next_pending_index: u64,
+    pub status: PayoutBatchStatus,
+    pub created_by: Principal,
+    pub created_at: Time,
+    pub updated_at: Time,
+}
+
+impl PayoutBatch {
+    pub fn new(
+        ledger_canister_id: Principal,
+        from_subaccount: Option<Subaccount>,
+        total: u64,
+        created_by: Principal,
+    ) -> Self {
+        Self {
+            ledger_canister_id,
+            from_subaccount,
+            total,
+            completed: 0,
+            failed: 0,
+            next_pending_index: 0,
+            status: PayoutBatchStatus::Pending,
+            created_by,
+            created_at: time(),
+            updated_at: time(),
+        }
+    }
+
+    pub fn pending(&self) -> u64 {
+        self.total.saturating_sub(self.completed + self.failed)
+    }
+
+    /// Count an item that moved from pending to completed or failed
+    pub fn settle_item(&mut self, status: &PayoutStatus) {
+        match status {
+            PayoutStatus::Pending => {}
+            PayoutStatus::Completed { .. } => self.completed += 1,
+            PayoutStatus::Failed { .. } => self.failed += 1,
+        }
+        self.update_status();
+    }
+
+    pub fn update_status(&mut self) {
+        self.updated_at = time();
+        if self.pending() > 0 {
+            self.status = PayoutBatchStatus::Pending;
+        } else if self.failed > 0 {
+            self.status = PayoutBatchStatus::CompletedWithFailures;
+        } else {
+            self.status = PayoutBatchStatus::Completed;
+        }
+    }
+
+    pub fn to_response(&self, id: u64, items: Vec<PayoutItem>) -> PayoutBatchResponse {
+        PayoutBatchResponse {
+            id,
+            status: self.status,
+            total: self.total,
+            completed: self.completed,
+            failed: self.failed,
+            pending: self.pending(),
+            items,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub struct PayoutBatchResponse {
+    pub id: u64,
+    pub status: PayoutBatchStatus,
+    pub total: u64,
+    pub completed: u64,
+    pub failed: u64,
+    pub pending: u64,
+    pub items: Vec<PayoutItem>,
+    pub created_at: Time,
+    pub updated_at: Time,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::runtime::MockRuntime;
+
+    #[test]
+    fn status_follows_the_settled_items() {
+        MockRuntime::new().install();
+        let mut batch = PayoutBatch::new(Principal::anonymous(), None, 2, Principal::anonymous());
+
+        batch.settle_item(&PayoutStatus::Completed {
+            block_index: 1u64.into(),
+        });
+        assert_eq!(batch.status, PayoutBatchStatus::Pending);
+        assert_eq!(batch.pending(), 1);
+
+        batch.settle_item(&PayoutStatus::Failed {
+            code: 1012,
+            message: "conflict".to_string(),
+        });
+        assert_eq!(batch.status, PayoutBatchStatus::CompletedWithFailures);
+        assert_eq!(batch.pending(), 0);
+    }
+}
@@ -0,0 +1,77 @@
+use candid::{CandidType, Nat};
+use icrc_ledger_types::icrc1::{
+    account::{Account, Subaccount},
+    transfer::{BlockIndex, Memo, TransferArg},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{api_error::ApiError, impl_storable_for, misc::generic::Time};
+
+impl_storable_for!(PayoutItem);
+
+/// The ledger deduplicates transfers for 24 hours, with an hour margin for the retry
+pub static PAYOUT_DEDUPLICATION_WINDOW_NANOS: u64 = 60 * 60 * 23 * 1_000_000_000;
+
+/// A failure is stored as the stable error code and message, so the stored layout does not depend on `ApiErrorType`.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum PayoutStatus {
+    Pending,
+    Completed { block_index: BlockIndex },
+    Failed { code: u32, message: String },
+}
+
+impl PayoutStatus {
+    pub fn failed(error: &ApiError) -> Self {
+        PayoutStatus::Failed {
+            code: error.code(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// A single transfer of a `PayoutBatch`, stored by `(batch id, index)`.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub struct PayoutItem {
+    pub to: Account,
+    pub amount: Nat,
+    pub memo: Option<Memo>,
+    pub status: PayoutStatus,
+    pub attempts: u32,
+    /// Set on the first attempt and reused by every retry, so a retry is deduplicated by the ledger
+    pub created_at_time: Option<u64>,
+}
+
+impl PayoutItem {
+    pub fn new(to: Account, amount: Nat, memo: Option<Memo>) -> Self {
+        Self {
+            to,
+            amount,
+            memo,
+            status: PayoutStatus::Pending,
+            attempts: 0,
+            created_at_time: None,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        matches!(self.status, PayoutStatus::Pending)
+    }
+
+    /// An attempted payout whose `created_at_time` is too old for the ledger deduplication
+    pub fn is_outside_deduplication_window(&self, now: Time) -> bool {
+        self.created_at_time.is_some_and(|created_at_time| {
+            now.saturating_sub(created_at_time) > PAYOUT_DEDUPLICATION_WINDOW_NANOS
+        })
+    }
+
+    pub fn to_transfer_arg(&self, from_subaccount: Option<Subaccount>) -> TransferArg {
+        TransferArg {
+            from_subaccount,
+            to: self.to,
+            fee: None,
+            created_at_time: self.created_at_time,
+            memo: self.memo.clone(),
+            amount: self.amount.clone(),
+        }
+    }
+}
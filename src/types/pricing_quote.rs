@@ -0,0 +1,102 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    impl_storable_for,
+    misc::generic::{
+        Time, CANISTER_SPINUP_CYCLES, MIN_CYCLES_FOR_CANISTER_SPINUP,
+        MIN_CYCLES_FOR_CANISTER_SPINUP_DEV, MIN_CYCLES_FOR_PROJECT_CANISTER_SPINUP,
+        XDR_FEE_FOR_CANISTER, XDR_FEE_FOR_CANISTER_DEV, XDR_FEE_FOR_PROJECT,
+    },
+};
+
+impl_storable_for!(PricingQuote);
+
+/// The subnet size that `CANISTER_SPINUP_CYCLES` is the creation fee for
+pub static BASE_SUBNET_SIZE: u64 = 13;
+
+/// What to price: canisters created on a subnet of `subnet_size` nodes, each with initial cycles, plus a service fee.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DeploymentPlan {
+    pub canister_count: u64,
+    pub initial_cycles_per_canister: u128,
+    pub subnet_size: u64,
+    /// In XDR permyriad, e.g. 5_000 for 0.5 XDR
+    pub service_fee_xdr_permyriad: u64,
+}
+
+impl DeploymentPlan {
+    /// Two project canisters, priced at `MIN_CYCLES_FOR_PROJECT_SPINUP` plus `XDR_FEE_FOR_PROJECT`
+    pub fn project() -> Self {
+        Self {
+            canister_count: 2,
+            initial_cycles_per_canister: (MIN_CYCLES_FOR_PROJECT_CANISTER_SPINUP
+                - CANISTER_SPINUP_CYCLES) as u128,
+            subnet_size: BASE_SUBNET_SIZE,
+            service_fee_xdr_permyriad: XDR_FEE_FOR_PROJECT,
+        }
+    }
+
+    /// A single canister, priced at `MIN_CYCLES_FOR_CANISTER_SPINUP` plus `XDR_FEE_FOR_CANISTER`
+    pub fn canister() -> Self {
+        Self {
+            canister_count: 1,
+            initial_cycles_per_canister: (MIN_CYCLES_FOR_CANISTER_SPINUP - CANISTER_SPINUP_CYCLES)
+                as u128,
+            subnet_size: BASE_SUBNET_SIZE,
+            service_fee_xdr_permyriad: XDR_FEE_FOR_CANISTER,
+        }
+    }
+
+    /// A single canister, priced at `MIN_CYCLES_FOR_CANISTER_SPINUP_DEV` plus `XDR_FEE_FOR_CANISTER_DEV`
+    pub fn canister_dev() -> Self {
+        Self {
+            canister_count: 1,
+            initial_cycles_per_canister: (MIN_CYCLES_FOR_CANISTER_SPINUP_DEV
+                - CANISTER_SPINUP_CYCLES) as u128,
+            subnet_size: BASE_SUBNET_SIZE,
+            service_fee_xdr_permyriad: XDR_FEE_FOR_CANISTER_DEV,
+        }
+    }
+
+    pub fn with_subnet_size(mut self, subnet_size: u64) -> Self {
+        self.subnet_size = subnet_size;
+        self
+    }
+}
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct QuoteLineItem {
+    pub label: String,
+    pub quantity: u64,
+    pub cycles: u128,
+    pub xdr_permyriad: u64,
+    pub icp_e8s: u64,
+}
+
+/// An itemised price of a `DeploymentPlan` at a fixed ICP/XDR rate, valid until `expires_at`.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PricingQuote {
+    pub plan: DeploymentPlan,
+    pub items: Vec<QuoteLineItem>,
+    pub total_cycles: u128,
+    pub total_xdr_permyriad: u64,
+    /// The sum of the ICP of the items, each rounded up
+    pub total_icp_e8s: u64,
+    pub xdr_permyriad_per_icp: u64,
+    pub rate_timestamp_seconds: u64,
+    pub created_at: Time,
+    pub expires_at: Time,
+    /// The block of the payment that settled the quote
+    pub paid_block_index: Option<u64>,
+}
+
+impl PricingQuote {
+    pub fn is_expired(&self, at: Time) -> bool {
+        at > self.expires_at
+    }
+
+    pub fn is_paid(&self) -> bool {
+        self.paid_block_index.is_some()
+    }
+}
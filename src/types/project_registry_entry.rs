@@ -1,8 +1,7 @@
 use candid::{CandidType, Principal};
-use ic_cdk::api::time;
 use serde::{Deserialize, Serialize};
 
-use crate::impl_storable_for;
+use crate::{impl_storable_for, misc::runtime::time};
 
 impl_storable_for!(ProjectRegistryEntry);
 
@@ -4,6 +4,30 @@ use crate::api_error::ApiError;
 
 pub type CanisterResult<T> = Result<T, Box<ApiError>>;
 
+pub trait ErrorContext<T> {
+    /// Add context to the error while it travels up, see `ApiError::with_context`.
+    fn with_context<S: std::fmt::Display>(self, message: S, method_name: &str)
+        -> CanisterResult<T>;
+
+    /// Report the error to the registered `ErrorTelemetry`, see `ApiError::record`.
+    fn record_error(self) -> CanisterResult<T>;
+}
+
+impl<T> ErrorContext<T> for CanisterResult<T> {
+    fn with_context<S: std::fmt::Display>(
+        self,
+        message: S,
+        method_name: &str,
+    ) -> CanisterResult<T> {
+        self.map_err(|err| err.with_context(message).add_method_name(method_name))
+    }
+
+    fn record_error(self) -> CanisterResult<T> {
+        self.map_err(|err| err.record())
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(CandidType, Deserialize)]
 pub enum CanisterCallResult<T> {
     Ok(T),
@@ -0,0 +1,30 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::misc::generic::Time;
+
+/// The projected time until a canister reaches its freezing threshold.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RunwayForecast {
+    pub canister_id: Principal,
+    pub cycles: u128,
+    pub freezing_threshold_cycles: u128,
+    /// The observed burn over the window, at least the idle burn
+    pub burn_rate_cycles_per_day: u128,
+    pub idle_cycles_burned_per_day: u128,
+    pub memory_size: u64,
+    /// `None` when the canister does not burn cycles
+    pub runway_seconds: Option<u64>,
+    pub runway_days: Option<u64>,
+    /// The projected time the canister freezes
+    pub freezes_at: Option<Time>,
+    pub samples: u64,
+    pub window_seconds: u64,
+    pub forecast_at: Time,
+}
+
+impl RunwayForecast {
+    pub fn is_frozen(&self) -> bool {
+        self.cycles <= self.freezing_threshold_cycles
+    }
+}
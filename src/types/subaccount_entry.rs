@@ -0,0 +1,29 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+};
+
+impl_storable_for!(SubaccountEntry);
+
+/// The inputs a subaccount was derived from, used to attribute incoming funds.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SubaccountEntry {
+    pub domain: String,
+    pub principal: Principal,
+    pub nonce: u64,
+    pub created_at: Time,
+}
+
+impl SubaccountEntry {
+    pub fn new(domain: &str, principal: Principal, nonce: u64) -> Self {
+        Self {
+            domain: domain.to_string(),
+            principal,
+            nonce,
+            created_at: time(),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+};
+
+impl_storable_for!(SweepAccount);
+
+/// A registered deposit subaccount (`Subaccount::from(principal)`), the swept amounts are credited in the `BookLedger`.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SweepAccount {
+    pub total_swept_e8s: u64,
+    pub last_swept_at: Option<Time>,
+    pub registered_at: Time,
+}
+
+impl Default for SweepAccount {
+    fn default() -> Self {
+        Self {
+            total_swept_e8s: 0,
+            last_swept_at: None,
+            registered_at: time(),
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::impl_storable_for;
+
+impl_storable_for!(SweepRecord);
+
+/// A single sweep of a deposit subaccount to the treasury, executed through the `TransferJournal`
+/// and credited in the `BookLedger` once the transfer completed.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub struct SweepRecord {
+    pub principal: Principal,
+    pub journal_id: u64,
+    pub amount_e8s: u64,
+    pub credited: bool,
+}
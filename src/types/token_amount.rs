@@ -0,0 +1,307 @@
+use std::{cmp::Ordering, fmt};
+
+use candid::{CandidType, Nat};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::ApiError,
+    conversions::{try_nat_to_u128, try_u128_to_u64},
+    result::CanisterResult,
+};
+
+pub static ICP_DECIMALS: u8 = 8;
+/// Cycles expressed in trillions, one unit is a single cycle
+pub static CYCLES_DECIMALS: u8 = 12;
+/// XDR expressed in permyriad (1/10_000) as used by the CMC
+pub static XDR_PERMYRIAD_DECIMALS: u8 = 4;
+
+// 1 XDR converts to 1 trillion cycles, so 1 XDR permyriad is 10^8 cycles
+const CYCLES_PER_XDR_PERMYRIAD: u128 = 100_000_000;
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Towards zero
+    Down,
+    /// Away from zero
+    Up,
+    /// To the nearest value, halves away from zero
+    HalfUp,
+}
+
+/// An exact token amount in base units (e.g. e8s) together with its number of decimals.
+///
+/// Amounts with different decimals are not ordered, `partial_cmp` returns `None` for them.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct TokenAmount {
+    units: u128,
+    decimals: u8,
+}
+
+impl TokenAmount {
+    pub fn new(units: u128, decimals: u8) -> Self {
+        Self { units, decimals }
+    }
+
+    pub fn zero(decimals: u8) -> Self {
+        Self::new(0, decimals)
+    }
+
+    pub fn from_e8s(e8s: u64) -> Self {
+        Self::new(e8s as u128, ICP_DECIMALS)
+    }
+
+    pub fn from_cycles(cycles: u128) -> Self {
+        Self::new(cycles, CYCLES_DECIMALS)
+    }
+
+    pub fn from_xdr_permyriad(xdr_permyriad: u64) -> Self {
+        Self::new(xdr_permyriad as u128, XDR_PERMYRIAD_DECIMALS)
+    }
+
+    pub fn from_nat(value: &Nat, decimals: u8) -> CanisterResult<Self> {
+        let units = try_nat_to_u128(value)?;
+        Ok(Self::new(units, decimals))
+    }
+
+    pub fn units(&self) -> u128 {
+        self.units
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.units == 0
+    }
+
+    pub fn to_nat(&self) -> Nat {
+        Nat::from(self.units)
+    }
+
+    pub fn to_u64(&self) -> CanisterResult<u64> {
+        try_u128_to_u64(self.units)
+    }
+
+    pub fn checked_add(&self, other: &Self) -> CanisterResult<Self> {
+        self.ensure_same_decimals(other, "checked_add")?;
+        self.units
+            .checked_add(other.units)
+            .map(|units| Self::new(units, self.decimals))
+            .ok_or_else(|| overflow("checked_add"))
+    }
+
+    pub fn checked_sub(&self, other: &Self) -> CanisterResult<Self> {
+        self.ensure_same_decimals(other, "checked_sub")?;
+        self.units
+            .checked_sub(other.units)
+            .map(|units| Self::new(units, self.decimals))
+            .ok_or_else(|| {
+                ApiError::bad_request(&format!("Can't subtract {} from {}", other, self))
+                    .add_method_name("TokenAmount::checked_sub")
+                    .add_source("toolkit_utils")
+            })
+    }
+
+    /// Compare two amounts, an error when the decimals differ
+    pub fn checked_cmp(&self, other: &Self) -> CanisterResult<Ordering> {
+        self.ensure_same_decimals(other, "checked_cmp")?;
+        Ok(self.units.cmp(&other.units))
+    }
+
+    pub fn checked_mul(&self, factor: u128) -> CanisterResult<Self> {
+        self.units
+            .checked_mul(factor)
+            .map(|units| Self::new(units, self.decimals))
+            .ok_or_else(|| overflow("checked_mul"))
+    }
+
+    pub fn checked_div(&self, divisor: u128, rounding: RoundingMode) -> CanisterResult<Self> {
+        div_round(self.units, divisor, rounding, "checked_div")
+            .map(|units| Self::new(units, self.decimals))
+    }
+
+    /// Multiply by `numerator / denominator`, rounding once at the end
+    pub fn checked_mul_div(
+        &self,
+        numerator: u128,
+        denominator: u128,
+        rounding: RoundingMode,
+    ) -> CanisterResult<Self> {
+        let product = self
+            .units
+            .checked_mul(numerator)
+            .ok_or_else(|| overflow("checked_mul_div"))?;
+        div_round(product, denominator, rounding, "checked_mul_div")
+            .map(|units| Self::new(units, self.decimals))
+    }
+
+    /// Convert to another number of decimals, e.g. e8s to e12s
+    pub fn rescale(&self, decimals: u8, rounding: RoundingMode) -> CanisterResult<Self> {
+        if decimals >= self.decimals {
+            let factor = pow10(decimals - self.decimals)?;
+            self.units
+                .checked_mul(factor)
+                .map(|units| Self::new(units, decimals))
+                .ok_or_else(|| overflow("rescale"))
+        } else {
+            let divisor = pow10(self.decimals - decimals)?;
+            div_round(self.units, divisor, rounding, "rescale")
+                .map(|units| Self::new(units, decimals))
+        }
+    }
+
+    /// The cycles an ICP amount converts to at the CMC rate
+    pub fn icp_to_cycles(&self, xdr_permyriad_per_icp: u64) -> CanisterResult<Self> {
+        let e8s = self.rescale(ICP_DECIMALS, RoundingMode::Down)?;
+        // cycles = e8s * xdr_permyriad_per_icp * 10^12 / (10^4 * 10^8)
+        e8s.checked_mul(xdr_permyriad_per_icp as u128)
+            .map(|amount| Self::new(amount.units, CYCLES_DECIMALS))
+    }
+
+    /// The ICP amount that converts to these cycles at the CMC rate
+    pub fn cycles_to_icp(
+        &self,
+        xdr_permyriad_per_icp: u64,
+        rounding: RoundingMode,
+    ) -> CanisterResult<Self> {
+        let cycles = self.rescale(CYCLES_DECIMALS, rounding)?;
+        div_round(
+            cycles.units,
+            xdr_permyriad_per_icp as u128,
+            rounding,
+            "cycles_to_icp",
+        )
+        .map(|units| Self::new(units, ICP_DECIMALS))
+    }
+
+    /// The cycles an XDR permyriad amount converts to
+    pub fn xdr_to_cycles(&self) -> CanisterResult<Self> {
+        let xdr = self.rescale(XDR_PERMYRIAD_DECIMALS, RoundingMode::Down)?;
+        xdr.checked_mul(CYCLES_PER_XDR_PERMYRIAD)
+            .map(|amount| Self::new(amount.units, CYCLES_DECIMALS))
+    }
+
+    /// The ICP amount that is worth this XDR permyriad amount at the CMC rate
+    pub fn xdr_to_icp(
+        &self,
+        xdr_permyriad_per_icp: u64,
+        rounding: RoundingMode,
+    ) -> CanisterResult<Self> {
+        self.xdr_to_cycles()?
+            .cycles_to_icp(xdr_permyriad_per_icp, rounding)
+    }
+
+    /// Format with a symbol, e.g. `1.5 ICP`
+    pub fn format(&self, symbol: &str) -> String {
+        format!("{} {}", self, symbol)
+    }
+
+    fn ensure_same_decimals(&self, other: &Self, method_name: &str) -> CanisterResult<()> {
+        if self.decimals != other.decimals {
+            return Err(ApiError::bad_request(&format!(
+                "Token amounts have different decimals ({} and {})",
+                self.decimals, other.decimals
+            ))
+            .add_method_name(format!("TokenAmount::{}", method_name))
+            .add_source("toolkit_utils"));
+        }
+        Ok(())
+    }
+}
+
+impl PartialOrd for TokenAmount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self.decimals == other.decimals).then(|| self.units.cmp(&other.units))
+    }
+}
+
+/// The amount in whole tokens without trailing zeros, e.g. `1.5` for 150_000_000 e8s
+impl fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.decimals == 0 {
+            return write!(f, "{}", self.units);
+        }
+
+        // beyond 38 decimals the divisor overflows, but every u128 is then below one token
+        let (whole, fraction) = match 10u128.checked_pow(self.decimals as u32) {
+            Some(divisor) => (self.units / divisor, self.units % divisor),
+            None => (0, self.units),
+        };
+        let fraction = format!("{:0>width$}", fraction, width = self.decimals as usize);
+        let fraction = fraction.trim_end_matches('0');
+
+        if fraction.is_empty() {
+            write!(f, "{}", whole)
+        } else {
+            write!(f, "{}.{}", whole, fraction)
+        }
+    }
+}
+
+fn pow10(exponent: u8) -> CanisterResult<u128> {
+    10u128
+        .checked_pow(exponent as u32)
+        .ok_or_else(|| overflow("rescale"))
+}
+
+fn div_round(
+    value: u128,
+    divisor: u128,
+    rounding: RoundingMode,
+    method_name: &str,
+) -> CanisterResult<u128> {
+    if divisor == 0 {
+        return Err(ApiError::bad_request("Division by zero")
+            .add_method_name(format!("TokenAmount::{}", method_name))
+            .add_source("toolkit_utils"));
+    }
+
+    let quotient = value / divisor;
+    let remainder = value % divisor;
+
+    let round_up = match rounding {
+        RoundingMode::Down => false,
+        RoundingMode::Up => remainder > 0,
+        RoundingMode::HalfUp => remainder >= divisor - remainder,
+    };
+
+    if round_up && remainder > 0 {
+        Ok(quotient + 1)
+    } else {
+        Ok(quotient)
+    }
+}
+
+fn overflow(method_name: &str) -> Box<ApiError> {
+    ApiError::bad_request("Token amount overflow")
+        .add_method_name(format!("TokenAmount::{}", method_name))
+        .add_source("toolkit_utils")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::runtime::MockRuntime;
+
+    #[test]
+    fn amounts_with_different_decimals_are_not_ordered() {
+        let icp = TokenAmount::from_e8s(100_000_000);
+        let cycles = TokenAmount::from_cycles(1_000_000_000_000);
+
+        assert_eq!(icp.partial_cmp(&cycles), None);
+        assert!(TokenAmount::from_e8s(1) < TokenAmount::from_e8s(2));
+
+        MockRuntime::new().install();
+        assert!(icp.checked_cmp(&cycles).is_err());
+    }
+
+    #[test]
+    fn display_handles_more_decimals_than_u128_digits() {
+        assert_eq!(TokenAmount::new(150_000_000, 8).to_string(), "1.5");
+        assert_eq!(
+            TokenAmount::new(5, 40).to_string(),
+            format!("0.{}5", "0".repeat(39))
+        );
+    }
+}
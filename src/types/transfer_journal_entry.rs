@@ -0,0 +1,88 @@
+use candid::{CandidType, Principal};
+use ic_ledger_types::{
+    AccountIdentifier, BlockIndex, Memo, Subaccount, Timestamp, Tokens, TransferArgs,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+};
+
+impl_storable_for!(TransferJournalEntry);
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum TransferStatus {
+    Pending,
+    Completed { block_index: BlockIndex },
+    Failed { reason: String },
+}
+
+/// The intent of an ICP transfer, the ledger arguments are derived from it on every attempt
+/// so a retry sends exactly the same transfer and is deduplicated by the ledger.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub struct TransferJournalEntry {
+    pub ledger_canister_id: Principal,
+    pub from_subaccount: Option<Subaccount>,
+    pub to: AccountIdentifier,
+    pub amount_e8s: u64,
+    pub fee_e8s: u64,
+    pub memo: u64,
+    pub created_at_time: u64,
+    pub status: TransferStatus,
+    pub attempts: u32,
+    pub created_at: Time,
+    pub updated_at: Time,
+}
+
+impl TransferJournalEntry {
+    pub fn new(
+        ledger_canister_id: Principal,
+        from_subaccount: Option<Subaccount>,
+        to: AccountIdentifier,
+        amount_e8s: u64,
+        fee_e8s: u64,
+        memo: u64,
+    ) -> Self {
+        Self {
+            ledger_canister_id,
+            from_subaccount,
+            to,
+            amount_e8s,
+            fee_e8s,
+            memo,
+            created_at_time: time(),
+            status: TransferStatus::Pending,
+            attempts: 0,
+            created_at: time(),
+            updated_at: time(),
+        }
+    }
+
+    pub fn to_transfer_args(&self) -> TransferArgs {
+        TransferArgs {
+            memo: Memo(self.memo),
+            amount: Tokens::from_e8s(self.amount_e8s),
+            fee: Tokens::from_e8s(self.fee_e8s),
+            from_subaccount: self.from_subaccount,
+            to: self.to,
+            created_at_time: Some(Timestamp {
+                timestamp_nanos: self.created_at_time,
+            }),
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.status == TransferStatus::Pending
+    }
+
+    pub fn set_status(&mut self, status: TransferStatus) {
+        self.status = status;
+        self.updated_at = time();
+    }
+
+    pub fn add_attempt(&mut self) {
+        self.attempts += 1;
+        self.updated_at = time();
+    }
+}
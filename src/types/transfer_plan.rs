@@ -0,0 +1,99 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::{api_error::ApiError, result::CanisterResult};
+
+/// Who pays the ledger fees of a transfer.
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum FeePayer {
+    /// The fees are charged on top of the amount, the recipient receives the full amount.
+    Sender,
+    /// The fees are deducted from the amount, the sender spends exactly the amount.
+    Recipient,
+}
+
+/// The amounts of a transfer that can take multiple ledger transfers (e.g. approve -> subaccount -> CMC).
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct TransferPlan {
+    /// The amount that leaves the sender's account, including all fees
+    pub gross_e8s: u64,
+    /// The amount that arrives at the final recipient
+    pub net_e8s: u64,
+    /// The ledger fee of a single transfer
+    pub fee_e8s: u64,
+    /// The total fees of all transfers
+    pub total_fee_e8s: u64,
+    pub transfers: u64,
+    pub fee_payer: FeePayer,
+}
+
+impl TransferPlan {
+    /// Plan a single ledger transfer
+    pub fn single(amount_e8s: u64, fee_e8s: u64, fee_payer: FeePayer) -> CanisterResult<Self> {
+        Self::new(amount_e8s, fee_e8s, fee_payer, 1)
+    }
+
+    /// Plan `transfers` consecutive ledger transfers that each charge `fee_e8s`
+    /// # Returns
+    /// * `Result<TransferPlan, ApiError>` - A `BadRequest` error when the amount can't cover the fees
+    pub fn new(
+        amount_e8s: u64,
+        fee_e8s: u64,
+        fee_payer: FeePayer,
+        transfers: u64,
+    ) -> CanisterResult<Self> {
+        let total_fee_e8s = fee_e8s.checked_mul(transfers).ok_or_else(|| {
+            ApiError::bad_request("Transfer fees overflow")
+                .add_method_name("TransferPlan::new")
+                .add_source("toolkit_utils")
+        })?;
+
+        let (gross_e8s, net_e8s) = match fee_payer {
+            FeePayer::Sender => {
+                let gross = amount_e8s.checked_add(total_fee_e8s).ok_or_else(|| {
+                    ApiError::bad_request("Amount plus fees overflows")
+                        .add_method_name("TransferPlan::new")
+                        .add_source("toolkit_utils")
+                })?;
+                (gross, amount_e8s)
+            }
+            FeePayer::Recipient => {
+                let net = amount_e8s
+                    .checked_sub(total_fee_e8s)
+                    .filter(|net| *net > 0)
+                    .ok_or_else(|| {
+                        ApiError::bad_request(&format!(
+                            "Amount of {} e8s does not cover the fees of {} e8s",
+                            amount_e8s, total_fee_e8s
+                        ))
+                        .add_method_name("TransferPlan::new")
+                        .add_source("toolkit_utils")
+                    })?;
+                (amount_e8s, net)
+            }
+        };
+
+        if net_e8s == 0 {
+            return Err(
+                ApiError::bad_request("Transfer amount must be greater than zero")
+                    .add_method_name("TransferPlan::new")
+                    .add_source("toolkit_utils"),
+            );
+        }
+
+        Ok(Self {
+            gross_e8s,
+            net_e8s,
+            fee_e8s,
+            total_fee_e8s,
+            transfers,
+            fee_payer,
+        })
+    }
+
+    /// The amount of the transfer at `hop` (0 based) of the plan, each hop pays the fee of its own transfer.
+    pub fn amount_at(&self, hop: u64) -> u64 {
+        let remaining_fees = self.transfers.saturating_sub(hop + 1) * self.fee_e8s;
+        self.net_e8s + remaining_fees
+    }
+}
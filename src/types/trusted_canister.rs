@@ -0,0 +1,46 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    impl_storable_for,
+    misc::{
+        generic::Time,
+        runtime::{msg_caller, time},
+    },
+};
+
+impl_storable_for!(TrustedCanister);
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub struct TrustedCanister {
+    pub label: Option<String>,
+    pub added_by: Principal,
+    pub created_at: Time,
+}
+
+impl TrustedCanister {
+    pub fn new(label: Option<String>) -> Self {
+        Self {
+            label,
+            added_by: msg_caller(),
+            created_at: time(),
+        }
+    }
+
+    pub fn to_response(&self, canister_id: Principal) -> TrustedCanisterResponse {
+        TrustedCanisterResponse {
+            canister_id,
+            label: self.label.clone(),
+            added_by: self.added_by,
+            created_at: self.created_at,
+        }
+    }
+}
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub struct TrustedCanisterResponse {
+    pub canister_id: Principal,
+    pub label: Option<String>,
+    pub added_by: Principal,
+    pub created_at: Time,
+}
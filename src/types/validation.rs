@@ -3,7 +3,10 @@ use std::fmt;
 use candid::CandidType;
 use serde::{Deserialize, Serialize};
 
-use super::date_range::DateRange;
+use super::{
+    date_range::DateRange,
+    error_code::{ErrorParam, ValidationCode},
+};
 
 #[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
 pub struct Length {
@@ -15,6 +18,34 @@ pub struct Length {
 pub struct ValidationResponse {
     pub field: String,
     pub message: String,
+    pub code: Option<u32>,
+    pub params: Option<Vec<ErrorParam>>,
+}
+
+impl ValidationResponse {
+    /// A response without a code, only the message is shown
+    pub fn from_message<S: ToString, M: ToString>(field: S, message: M) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.to_string(),
+            code: None,
+            params: None,
+        }
+    }
+
+    pub fn new<S: ToString>(
+        field: S,
+        code: ValidationCode,
+        message: String,
+        params: Vec<ErrorParam>,
+    ) -> Self {
+        Self {
+            field: field.to_string(),
+            message,
+            code: Some(code.code()),
+            params: Some(params),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
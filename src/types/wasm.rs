@@ -1,10 +1,9 @@
 use candid::CandidType;
-use ic_cdk::api::time;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     impl_storable_for,
-    misc::{generic::Time, hash::generate_checksum},
+    misc::{generic::Time, hash::generate_checksum, runtime::time},
 };
 
 use super::{version::Version, wasm_details::WasmDetails};
@@ -0,0 +1,162 @@
+use std::cell::RefCell;
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::ApiError,
+    impl_storable_for,
+    misc::{generic::Time, runtime::time},
+    StaticStorageRef,
+};
+
+pub static DEFAULT_RECENT_ERRORS_CAPACITY: u64 = 100;
+
+static TYPE_PREFIX: &str = "type:";
+static METHOD_PREFIX: &str = "method:";
+static SOURCE_PREFIX: &str = "source:";
+
+thread_local! {
+    static ERROR_RECORDER: RefCell<Option<ErrorTelemetry>> = const { RefCell::new(None) };
+}
+
+impl_storable_for!(ErrorRecord);
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone)]
+pub struct ErrorRecord {
+    pub code: u32,
+    pub error_type: String,
+    pub message: String,
+    pub method_name: Option<String>,
+    pub source: Option<String>,
+    pub recorded_at: Time,
+}
+
+#[derive(Debug, CandidType, Serialize, Deserialize, Clone, Default)]
+pub struct ErrorTelemetryResponse {
+    pub total: u64,
+    pub by_type: Vec<(String, u64)>,
+    pub by_method: Vec<(String, u64)>,
+    pub by_source: Vec<(String, u64)>,
+    pub recent: Vec<ErrorRecord>,
+}
+
+/// Opt-in recorder that keeps stable error counters and a bounded list of recent errors.
+///
+/// The canister owns the stable memory, register the recorder once in `init` and `post_upgrade`:
+/// ```ignore
+/// ErrorTelemetry::new(&ERROR_COUNTERS, &RECENT_ERRORS).register();
+/// ```
+/// Errors are reported with `ApiError::record` or `ErrorContext::record_error`.
+#[derive(Clone, Copy)]
+pub struct ErrorTelemetry {
+    counters: StaticStorageRef<String, u64>,
+    recent: StaticStorageRef<u64, ErrorRecord>,
+    capacity: u64,
+}
+
+impl ErrorTelemetry {
+    pub fn new(
+        counters: StaticStorageRef<String, u64>,
+        recent: StaticStorageRef<u64, ErrorRecord>,
+    ) -> Self {
+        Self {
+            counters,
+            recent,
+            capacity: DEFAULT_RECENT_ERRORS_CAPACITY,
+        }
+    }
+
+    pub fn with_capacity(mut self, capacity: u64) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Make this recorder the one that `ApiError::record` reports into.
+    pub fn register(self) {
+        ERROR_RECORDER.with(|recorder| *recorder.borrow_mut() = Some(self));
+    }
+
+    /// The registered recorder, `None` when telemetry is not enabled.
+    pub fn registered() -> Option<Self> {
+        ERROR_RECORDER.with(|recorder| *recorder.borrow())
+    }
+
+    pub fn record(&self, error: &ApiError) {
+        let record = ErrorRecord {
+            code: error.code(),
+            error_type: error.error_type().to_string(),
+            message: error.message().to_string(),
+            method_name: error.method_name().map(|value| value.to_string()),
+            source: error.source().map(|value| value.to_string()),
+            recorded_at: time(),
+        };
+
+        self.increment(format!("{TYPE_PREFIX}{}", record.error_type));
+        if let Some(method_name) = &record.method_name {
+            self.increment(format!("{METHOD_PREFIX}{method_name}"));
+        }
+        if let Some(source) = &record.source {
+            self.increment(format!("{SOURCE_PREFIX}{source}"));
+        }
+
+        self.recent.with(|data| {
+            let mut data = data.borrow_mut();
+            let key = data.last_key_value().map(|(k, _)| k + 1).unwrap_or(1);
+            data.insert(key, record);
+
+            while data.len() > self.capacity {
+                match data.first_key_value() {
+                    Some((first_key, _)) => data.remove(&first_key),
+                    None => break,
+                };
+            }
+        });
+    }
+
+    pub fn get_summary(&self) -> ErrorTelemetryResponse {
+        let mut response = ErrorTelemetryResponse {
+            recent: self
+                .recent
+                .with(|data| data.borrow().iter().map(|(_, v)| v).rev().collect()),
+            ..Default::default()
+        };
+
+        self.counters.with(|data| {
+            for (key, count) in data.borrow().iter() {
+                if let Some(error_type) = key.strip_prefix(TYPE_PREFIX) {
+                    response.total += count;
+                    response.by_type.push((error_type.to_string(), count));
+                } else if let Some(method_name) = key.strip_prefix(METHOD_PREFIX) {
+                    response.by_method.push((method_name.to_string(), count));
+                } else if let Some(source) = key.strip_prefix(SOURCE_PREFIX) {
+                    response.by_source.push((source.to_string(), count));
+                }
+            }
+        });
+
+        response
+    }
+
+    pub fn clear(&self) {
+        self.counters.with(|data| {
+            let keys: Vec<String> = data.borrow().iter().map(|(k, _)| k).collect();
+            for key in keys {
+                data.borrow_mut().remove(&key);
+            }
+        });
+        self.recent.with(|data| {
+            let keys: Vec<u64> = data.borrow().iter().map(|(k, _)| k).collect();
+            for key in keys {
+                data.borrow_mut().remove(&key);
+            }
+        });
+    }
+
+    fn increment(&self, key: String) {
+        self.counters.with(|data| {
+            let count = data.borrow().get(&key).unwrap_or_default();
+            data.borrow_mut().insert(key, count + 1);
+        });
+    }
+}
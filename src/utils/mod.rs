@@ -1,4 +1,5 @@
 pub mod cell_storage;
+pub mod error_telemetry;
 pub mod storage_types;
 
 pub use cell_storage::*;